@@ -4,6 +4,7 @@ use std::error::Error;
 use log::{trace, debug, info, warn, error};
 use clap::{Command, Arg, ArgGroup, error::ErrorKind, value_parser, builder::NonEmptyStringValueParser};
 use enigo::*;
+use macmatic::DragOptions;
 
 
 
@@ -79,7 +80,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     match matches.subcommand() {
         Some(("list", _)) => {
             // list all windows
-            print!("\n{}\n", macmatic::WindowList::new().prettify());
+            print!("\n{}\n", macmatic::WindowList::new()?.prettify());
         }
         Some(("screenshot", sub_matches)) => {
             set_window_from_arg(&mut cmd, &mut bot);
@@ -103,29 +104,29 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     fn set_window_from_arg(cmd: &mut Command, bot: &mut macmatic::Bot) {
         let matches = cmd.get_matches_mut();
-        if matches.contains_id("window_name") {
+        let selected = if matches.contains_id("window_name") {
             let name = matches.get_one::<String>("window_name").unwrap();
             if name.starts_with('~') {
                 let regex: &str = &name[1..name.len() - 1];
-                bot.set_window_from_regex(regex);
+                bot.set_window_from_regex(regex)
             } else {
-                bot.set_window_from_name(name);
+                bot.set_window_from_name(name)
             }
         } else if matches.contains_id("window_id") {
             let id: i64 = *matches.get_one::<i64>("window_id").expect("Invalid window Id");
-            bot.set_window_from_id(id);
+            bot.set_window_from_id(id)
         } else {
             cmd.error(
                 ErrorKind::MissingRequiredArgument,
                 "Window name or id required"
                 )
                 .exit();
-        }
+        };
         trace!("Window found: {:#?}", bot.window);
-        if bot.window.is_none() {
+        if let Err(e) = selected {
             cmd.error(
                 ErrorKind::InvalidValue,
-                "Window not found"
+                format!("Window not found ({})", e)
                 )
                 .exit();
         }
@@ -138,7 +139,7 @@ fn example_find(bot: &mut macmatic::Bot, file: &Path) -> Result<(), Box<dyn Erro
     let wait_time = 800; // in millis
 
     bot.sleep(wait_time);
-    let rect = bot.find(&file).unwrap();
+    let rect = bot.find(file).unwrap();
     info!("Template found at {:#?}", rect);
 
     Ok(())
@@ -150,9 +151,7 @@ fn example_preview(bot: &mut macmatic::Bot) -> Result<(), Box<dyn Error>> {
     bot.activate_window()?;
     bot.sleep(wait_time);
     let rect = bot.find(Path::new("examples/img/W.png")).unwrap();
-    bot.mouse_down_on(rect.x, rect.y)?;
-    bot.sleep(wait_time);
-    bot.mouse_up_on(rect.x + rect.width, rect.y + rect.height)?;
+    bot.drag((rect.x, rect.y), (rect.x + rect.width, rect.y + rect.height), &DragOptions::default())?;
 
     bot.sleep(wait_time);
     bot.key_down(Key::Control)?;
@@ -0,0 +1,244 @@
+//! Screen capture backends
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn};
+
+use opencv::{
+    core::Mat,
+    prelude::*,
+    imgcodecs,
+    imgproc,
+};
+
+use crate::{error, Rect, Result};
+
+/// Largest difference on a color channel which does not count as a pixel change,
+/// so that compression and anti-aliasing noise is ignored when comparing frames.
+pub const PIXEL_TOLERANCE: u8 = 16;
+
+/// Structure representing a captured image of a window.
+///
+/// Pixels are stored in BGRA order, 4 bytes per pixel, rows are contiguous.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates a new `Frame` from raw BGRA pixels, which must hold exactly `width * height * 4` bytes.
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Result<Self> {
+        if data.len() != width as usize * height as usize * 4 {
+            return Err(error::Error { kind: error::ErrorKind::CaptureFailed, message: format!("{} bytes cannot hold a {} x {} BGRA frame", data.len(), width, height) });
+        }
+        Ok(Frame { width, height, data })
+    }
+
+    /// Returns the width of the frame, in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the frame, in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the raw BGRA pixels.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consumes the frame and returns its raw BGRA pixels.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Loads a `Frame` from an image file (PNG, JPEG...).
+    pub fn from_file(file: &Path) -> Result<Self> {
+        let img = imgcodecs::imread(&file.to_string_lossy(), imgcodecs::IMREAD_COLOR)?;
+        if img.rows() == 0 || img.cols() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Cannot read image {}", file.display())).into());
+        }
+        let mut bgra = Mat::default();
+        imgproc::cvt_color(&img, &mut bgra, imgproc::COLOR_BGR2BGRA, 0)?;
+        Frame::new(bgra.cols() as u32, bgra.rows() as u32, bgra.data_bytes()?.to_vec())
+    }
+
+    /// Returns the part of the frame covered by the rectangle, clipped to the frame.
+    pub fn crop(&self, rect: &Rect) -> Frame {
+        let (x, y) = (rect.x.min(self.width), rect.y.min(self.height));
+        let rect = Rect::new(x, y, rect.width.min(self.width - x), rect.height.min(self.height - y));
+        let (stride, offset) = (self.width as usize * 4, rect.x as usize * 4);
+        let mut data = Vec::with_capacity(rect.width as usize * rect.height as usize * 4);
+        for row in rect.y..rect.y + rect.height {
+            let start = row as usize * stride + offset;
+            data.extend_from_slice(&self.data[start..start + rect.width as usize * 4]);
+        }
+        Frame { width: rect.width, height: rect.height, data }
+    }
+
+    /// Returns the proportion of pixels which differ between two frames, 1.0 if their sizes differ.
+    ///
+    /// A pixel differs when one of its channels differs by more than `PIXEL_TOLERANCE`.
+    pub fn difference(&self, other: &Frame) -> f64 {
+        if self.width != other.width || self.height != other.height {
+            return 1.0;
+        }
+        let pixels = self.width as usize * self.height as usize;
+        if pixels == 0 {
+            return 0.0;
+        }
+        let changed = self.data.chunks_exact(4)
+            .zip(other.data.chunks_exact(4))
+            .filter(|(a, b)| a.iter().zip(b.iter()).any(|(x, y)| x.abs_diff(*y) > PIXEL_TOLERANCE))
+            .count();
+        changed as f64 / pixels as f64
+    }
+}
+
+/// A source of window frames.
+///
+/// Implement this trait to run image detection against something else than the live screen.
+/// Sources are shared between windows, which may be sent to other threads.
+pub trait FrameSource: fmt::Debug + Send + Sync {
+    /// Captures the current content of the window with the given id.
+    fn capture(&self, window_id: i64) -> Result<Frame>;
+}
+
+/// Replays frames loaded from image files, whatever the window.
+///
+/// Each capture returns the next frame of the sequence, the last frame is then repeated forever.
+///
+/// ```no_run
+/// use std::path::Path;
+/// use macmatic::{Bot, Bounds, Window, capture::ReplaySource, input::RecordingDriver};
+///
+/// let mut bot = Bot::new();
+/// bot.set_controller(RecordingDriver::new());
+/// bot.window = Some(Window::new(1, "Dialog", "App", Some(Bounds::new(0.0, 0.0, 120.0, 90.0))));
+/// // frame_1.png to frame_4.png, the button appears on frame 4
+/// bot.set_frame_source(ReplaySource::from_dir(Path::new("tests/fixtures/dialog")).unwrap());
+/// bot.click_on_image(Path::new("tests/fixtures/button.png"), 2000).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ReplaySource {
+    frames: Vec<Frame>,
+    next: AtomicUsize,
+}
+
+impl ReplaySource {
+    /// Creates a new `ReplaySource` from already loaded frames.
+    pub fn new(frames: Vec<Frame>) -> Self {
+        ReplaySource {
+            frames,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Creates a new `ReplaySource` serving a single image file.
+    pub fn from_file(file: &Path) -> Result<Self> {
+        Ok(ReplaySource::new(vec![Frame::from_file(file)?]))
+    }
+
+    /// Creates a new `ReplaySource` serving the PNG files of a directory, in numerical order.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")))
+            .collect();
+        if files.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No PNG file in {}", dir.display())).into());
+        }
+        // frame_2.png must come before frame_10.png
+        files.sort_by_key(|path| (ReplaySource::_frame_number(path), path.clone()));
+
+        let mut frames = vec![];
+        for file in files.iter() {
+            trace!("Loading frame {}", file.display());
+            frames.push(Frame::from_file(file)?);
+        }
+        Ok(ReplaySource::new(frames))
+    }
+
+    /// Returns the index of the next frame to be served.
+    pub fn position(&self) -> usize {
+        self.next.load(Ordering::SeqCst)
+    }
+
+    /// Restarts the replay from the first frame.
+    pub fn rewind(&self) {
+        self.next.store(0, Ordering::SeqCst);
+    }
+
+    fn _frame_number(file: &Path) -> u64 {
+        let stem = file.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        // only the last number counts, so that shot2_frame_10.png comes after shot2_frame_9.png
+        stem.rsplit(|c: char| !c.is_ascii_digit())
+            .find(|digits| !digits.is_empty())
+            .and_then(|digits| digits.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+impl FrameSource for ReplaySource {
+    fn capture(&self, _window_id: i64) -> Result<Frame> {
+        if self.frames.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No frame to replay").into());
+        }
+        let len = self.frames.len();
+        let next = self.next.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |next| (next < len).then_some(next + 1));
+        let index = next.unwrap_or_else(|next| next).min(len - 1);
+        trace!("Replaying frame {}/{}", index + 1, len);
+        Ok(self.frames[index].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_number_uses_last_digits() {
+        assert_eq!(ReplaySource::_frame_number(Path::new("frame_10.png")), 10);
+        assert_eq!(ReplaySource::_frame_number(Path::new("shot2_frame_9.png")), 9);
+        assert_eq!(ReplaySource::_frame_number(Path::new("2023-10-16_frame_042_v.png")), 42);
+        assert_eq!(ReplaySource::_frame_number(Path::new("frame.png")), 0);
+    }
+
+    #[test]
+    fn frame_checks_buffer_size() {
+        assert!(Frame::new(2, 2, vec![0; 16]).is_ok());
+        assert!(Frame::new(2, 2, vec![0; 15]).is_err());
+    }
+
+    #[test]
+    fn crop_is_clipped_to_the_frame() {
+        let data = (0..4 * 3 * 4).map(|i| i as u8).collect();
+        let frame = Frame::new(4, 3, data).unwrap();
+        let cropped = frame.crop(&Rect::new(1, 1, 2, 1));
+        assert_eq!((cropped.width(), cropped.height()), (2, 1));
+        assert_eq!(cropped.data(), &frame.data()[20..28]);
+        let clipped = frame.crop(&Rect::new(3, 2, 5, 5));
+        assert_eq!((clipped.width(), clipped.height()), (1, 1));
+        assert_eq!(clipped.data(), &frame.data()[44..48]);
+        let outside = frame.crop(&Rect::new(10, 10, 5, 5));
+        assert_eq!((outside.width(), outside.height()), (0, 0));
+    }
+
+    #[test]
+    fn difference_counts_changed_pixels() {
+        let a = Frame::new(2, 1, vec![0; 8]).unwrap();
+        let noisy = Frame::new(2, 1, vec![0, 0, 0, 0, 0, PIXEL_TOLERANCE, 0, 0]).unwrap();
+        let changed = Frame::new(2, 1, vec![0, 0, 0, 0, 0, 200, 0, 0]).unwrap();
+        assert_eq!(a.difference(&noisy), 0.0);
+        assert_eq!(a.difference(&changed), 0.5);
+        assert_eq!(a.difference(&Frame::new(1, 1, vec![0; 4]).unwrap()), 1.0);
+    }
+}
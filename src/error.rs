@@ -2,16 +2,89 @@
 
 use std::fmt;
 use std::error;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// Enumeration of different error kinds.
 #[derive(Debug)]
 pub enum ErrorKind {
     /// Occurs when template is not found in the window.
-    ImageNotFound,
+    ImageNotFound(Box<NotFound>),
     /// Occurs when error is raised at CoreFoundation level.
     CoreFoundation,
+    /// Occurs when a feature is not available on the current platform.
+    Unsupported,
+    /// Occurs when a waited condition is not met before the time-out.
+    Timeout,
+    /// Occurs when no window is selected, or when no window matches the selection.
+    NoWindow,
+    /// Occurs when several windows match a selection which must be unique.
+    AmbiguousWindow,
+    /// Occurs when no input controller is set.
+    NoController,
+    /// Occurs when the bounds of the window are unknown.
+    NoBounds,
+    /// Occurs when a window name regex cannot be compiled.
+    InvalidRegex(regex::Error),
+    /// Occurs when the window cannot be captured.
+    CaptureFailed,
+    /// Occurs when the matching options cannot be used with a template.
+    InvalidOptions,
+    /// Occurs when a search region does not overlap the window.
+    InvalidRegion,
     /// Allows to raise OpenCV errors directly.
     Opencv(opencv::Error),
+    /// Allows to raise I/O errors directly.
+    Io(io::Error),
+    /// Occurs when error is raised at X11 level, holding the underlying connection or reply error if any.
+    #[cfg(feature = "x11")]
+    X11(Option<Box<dyn error::Error + Send + Sync>>),
+}
+
+/// Diagnostics of a template search which did not find any match.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NotFound {
+    /// Identifier of the template (usually its path).
+    pub template: String,
+    /// Best score over all the attempts, if any location was scored.
+    pub best_score: Option<f64>,
+    /// Time-out of the search, zero when the search is not limited in time (e.g. when scrolling).
+    pub time_out: Duration,
+    /// Number of captures searched.
+    pub attempts: u32,
+    /// Path to a snapshot of the capture holding the best score, if one was saved.
+    pub snapshot: Option<PathBuf>,
+}
+
+impl NotFound {
+    /// Creates a new `NotFound`, without snapshot.
+    pub fn new(template: &str, best_score: Option<f64>, time_out: Duration, attempts: u32) -> Self {
+        NotFound {
+            template: template.to_string(),
+            best_score,
+            time_out,
+            attempts,
+            snapshot: None,
+        }
+    }
+}
+
+impl From<NotFound> for Error {
+    /// Converts search diagnostics to an `ImageNotFound` error.
+    fn from(not_found: NotFound) -> Error {
+        let best_score = not_found.best_score.map_or("none".to_string(), |score| format!("{:.3}", score));
+        let mut message = format!("Template {} not found (best score {} in {} attempts, time-out {} ms)",
+            not_found.template, best_score, not_found.attempts, not_found.time_out.as_millis());
+        if let Some(snapshot) = not_found.snapshot.as_ref() {
+            message.push_str(&format!(", snapshot {}", snapshot.display()));
+        }
+        Error {
+            message,
+            kind: ErrorKind::ImageNotFound(Box::new(not_found)),
+        }
+    }
 }
 
 /// Structure representing an error.
@@ -27,14 +100,39 @@ impl fmt::Display for Error {
     /// Formats the error for display.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.kind {
-            ErrorKind::ImageNotFound => write!(f, "Image not found: {}", self.message),
+            ErrorKind::ImageNotFound(_) => write!(f, "Image not found: {}", self.message),
             ErrorKind::CoreFoundation => write!(f, "Core Foundation: {}", self.message),
+            ErrorKind::Unsupported => write!(f, "Unsupported: {}", self.message),
+            ErrorKind::Timeout => write!(f, "Time-out: {}", self.message),
+            ErrorKind::NoWindow => write!(f, "No window: {}", self.message),
+            ErrorKind::AmbiguousWindow => write!(f, "Ambiguous window: {}", self.message),
+            ErrorKind::NoController => write!(f, "No controller: {}", self.message),
+            ErrorKind::NoBounds => write!(f, "No bounds: {}", self.message),
+            ErrorKind::InvalidRegex(ref e) => write!(f, "Invalid regex: {}", e),
+            ErrorKind::CaptureFailed => write!(f, "Capture failed: {}", self.message),
+            ErrorKind::InvalidOptions => write!(f, "Invalid options: {}", self.message),
+            ErrorKind::InvalidRegion => write!(f, "Invalid region: {}", self.message),
             ErrorKind::Opencv(ref e) => write!(f, "OpenCV Error: {}", e),
+            ErrorKind::Io(ref e) => write!(f, "I/O Error: {}", e),
+            #[cfg(feature = "x11")]
+            ErrorKind::X11(_) => write!(f, "X11: {}", self.message),
         }
     }
 }
 
-impl error::Error for Error { }
+impl error::Error for Error {
+    /// Returns the underlying error, if any.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::Opencv(ref e) => Some(e),
+            ErrorKind::Io(ref e) => Some(e),
+            ErrorKind::InvalidRegex(ref e) => Some(e),
+            #[cfg(feature = "x11")]
+            ErrorKind::X11(Some(ref e)) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl From<opencv::Error> for Error {
     /// Converts an OpenCV error to this error type.
@@ -45,3 +143,46 @@ impl From<opencv::Error> for Error {
         }
     }
 }
+
+impl From<regex::Error> for Error {
+    /// Converts a regex error to this error type.
+    fn from(err: regex::Error) -> Error {
+        Error {
+            message: err.to_string(),
+            kind: ErrorKind::InvalidRegex(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Converts an I/O error to this error type.
+    fn from(err: io::Error) -> Error {
+        Error {
+            message: err.to_string(),
+            kind: ErrorKind::Io(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_found_message() {
+        let err = Error::from(NotFound::new("button.png", None, Duration::from_millis(500), 0));
+        assert_eq!(err.message, "Template button.png not found (best score none in 0 attempts, time-out 500 ms)");
+        let err = Error::from(NotFound::new("button.png", Some(0.25), Duration::from_millis(500), 4));
+        assert_eq!(err.message, "Template button.png not found (best score 0.250 in 4 attempts, time-out 500 ms)");
+    }
+
+    #[cfg(feature = "x11")]
+    #[test]
+    fn x11_error_source() {
+        use std::error::Error as _;
+
+        let err = Error::from(x11rb::errors::ConnectError::InvalidScreen);
+        assert!(matches!(err.kind, ErrorKind::X11(Some(_))));
+        assert_eq!(err.source().map(|e| e.to_string()), Some(err.message.clone()));
+    }
+}
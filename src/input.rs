@@ -0,0 +1,323 @@
+//! Mouse and keyboard drivers
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use enigo::{Enigo, Key, KeyboardControllable, MouseButton, MouseControllable};
+
+use crate::{error, Result};
+
+/// A driver sending mouse and keyboard events on behalf of the `Bot`.
+///
+/// Coordinates are absolute screen coordinates.
+pub trait InputDriver: fmt::Debug {
+    /// Moves the mouse pointer to the given screen coordinates.
+    fn mouse_move_to(&mut self, x: i32, y: i32) -> Result<()>;
+    /// Returns the screen coordinates of the mouse pointer.
+    ///
+    /// Unsupported by default, the `Bot` then relies on the last position it moved the pointer to.
+    fn mouse_location(&self) -> Result<(i32, i32)> {
+        Err(error::Error { kind: error::ErrorKind::Unsupported, message: format!("{:?} cannot locate the mouse pointer", self) })
+    }
+    /// Pushes down the given mouse button.
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()>;
+    /// Releases the given mouse button.
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()>;
+    /// Scrolls the wheel under the pointer, positive values scrolling right and down.
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()>;
+    /// Presses down the given key.
+    fn key_down(&mut self, key: Key) -> Result<()>;
+    /// Releases the given key.
+    fn key_up(&mut self, key: Key) -> Result<()>;
+    /// Presses and releases the given key.
+    fn key_click(&mut self, key: Key) -> Result<()>;
+    /// Types a string.
+    fn key_sequence(&mut self, text: &str) -> Result<()>;
+}
+
+impl InputDriver for Enigo {
+    fn mouse_move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        MouseControllable::mouse_move_to(self, x, y);
+        Ok(())
+    }
+
+    fn mouse_location(&self) -> Result<(i32, i32)> {
+        Ok(MouseControllable::mouse_location(self))
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+        MouseControllable::mouse_down(self, button);
+        Ok(())
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+        MouseControllable::mouse_up(self, button);
+        Ok(())
+    }
+
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        if dx != 0 {
+            MouseControllable::mouse_scroll_x(self, dx);
+        }
+        if dy != 0 {
+            MouseControllable::mouse_scroll_y(self, dy);
+        }
+        Ok(())
+    }
+
+    fn key_down(&mut self, key: Key) -> Result<()> {
+        KeyboardControllable::key_down(self, key);
+        Ok(())
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<()> {
+        KeyboardControllable::key_up(self, key);
+        Ok(())
+    }
+
+    fn key_click(&mut self, key: Key) -> Result<()> {
+        KeyboardControllable::key_click(self, key);
+        Ok(())
+    }
+
+    fn key_sequence(&mut self, text: &str) -> Result<()> {
+        KeyboardControllable::key_sequence(self, text);
+        Ok(())
+    }
+}
+
+/// Structure describing a click: which button, how many times, and the modifier keys held meanwhile.
+///
+/// ```no_run
+/// use enigo::Key;
+/// use macmatic::{Bot, input::ClickSpec};
+///
+/// let mut bot = Bot::new();
+/// bot.click_with(200, 100, &ClickSpec::right()).unwrap();
+/// bot.click_with(200, 140, &ClickSpec::default().with_modifier(Key::Shift)).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClickSpec {
+    /// Mouse button to click.
+    pub button: MouseButton,
+    /// Number of clicks (2 for a double-click).
+    pub count: u32,
+    /// Keys held down during the clicks, pressed in order and released in reverse order.
+    pub modifiers: Vec<Key>,
+}
+
+impl Default for ClickSpec {
+    fn default() -> Self {
+        ClickSpec {
+            button: MouseButton::Left,
+            count: 1,
+            modifiers: vec![],
+        }
+    }
+}
+
+impl ClickSpec {
+    /// Creates a new `ClickSpec`, a single click of the given button.
+    pub fn new(button: MouseButton) -> Self {
+        ClickSpec { button, ..ClickSpec::default() }
+    }
+
+    /// Returns a single right click.
+    pub fn right() -> Self {
+        ClickSpec::new(MouseButton::Right)
+    }
+
+    /// Returns a single middle click.
+    pub fn middle() -> Self {
+        ClickSpec::new(MouseButton::Middle)
+    }
+
+    /// Returns a left double-click.
+    pub fn double() -> Self {
+        ClickSpec { count: 2, ..ClickSpec::default() }
+    }
+
+    /// Adds a key to hold down during the clicks.
+    pub fn with_modifier(mut self, key: Key) -> Self {
+        self.modifiers.push(key);
+        self
+    }
+}
+
+/// Enumeration of the directions to scroll the content of a window to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScrollDirection {
+    /// Scrolls towards the top of the content.
+    Up,
+    /// Scrolls towards the bottom of the content.
+    Down,
+    /// Scrolls towards the left of the content.
+    Left,
+    /// Scrolls towards the right of the content.
+    Right,
+}
+
+impl ScrollDirection {
+    /// Returns the wheel amounts (dx, dy) of a scroll of the given length in this direction.
+    pub fn amounts(self, length: i32) -> (i32, i32) {
+        match self {
+            ScrollDirection::Up => (0, -length),
+            ScrollDirection::Down => (0, length),
+            ScrollDirection::Left => (-length, 0),
+            ScrollDirection::Right => (length, 0),
+        }
+    }
+}
+
+/// Structure describing a drag-and-drop gesture with the left mouse button.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DragOptions {
+    /// Number of mouse moves from the source to the target, the last one landing on the target.
+    pub steps: u32,
+    /// Duration of the move from the source to the target.
+    pub duration: Duration,
+    /// Delay between the mouse down on the source and the first move.
+    pub hold_before_move: Duration,
+}
+
+impl Default for DragOptions {
+    fn default() -> Self {
+        DragOptions {
+            steps: 20,
+            duration: Duration::from_millis(400),
+            hold_before_move: Duration::from_millis(200),
+        }
+    }
+}
+
+/// An input event, as recorded by `RecordingDriver`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    /// The mouse pointer moved to the given screen coordinates.
+    MouseMove {
+        /// Horizontal screen coordinate.
+        x: i32,
+        /// Vertical screen coordinate.
+        y: i32,
+    },
+    /// A mouse button was pushed down.
+    MouseDown(MouseButton),
+    /// A mouse button was released.
+    MouseUp(MouseButton),
+    /// The mouse wheel was scrolled.
+    Scroll {
+        /// Horizontal amount, positive to the right.
+        dx: i32,
+        /// Vertical amount, positive downwards.
+        dy: i32,
+    },
+    /// A key was pressed down.
+    KeyDown(Key),
+    /// A key was released.
+    KeyUp(Key),
+    /// A key was pressed and released.
+    KeyClick(Key),
+    /// A string was typed.
+    Text(String),
+}
+
+/// A driver recording every event instead of sending it, for tests and dry runs.
+///
+/// Clones share the same event log, so keep one to inspect what the `Bot` did.
+///
+/// ```
+/// use std::time::Duration;
+/// use enigo::{Key, MouseButton};
+/// use macmatic::{Bot, Bounds, Window};
+/// use macmatic::input::{InputEvent, RecordingDriver};
+///
+/// let recorder = RecordingDriver::new();
+/// let mut bot = Bot::new();
+/// bot.set_controller(recorder.clone());
+/// bot.set_wait_time(Duration::ZERO);
+/// bot.set_high_dpi_ratio(2);
+/// bot.window = Some(Window::new(1, "Untitled", "TextEdit", Some(Bounds::new(100.0, 50.0, 800.0, 600.0))));
+///
+/// bot.click(200, 100).unwrap();
+/// bot.writeln("foo").unwrap();
+///
+/// assert_eq!(recorder.events(), vec![
+///     InputEvent::MouseMove { x: 200, y: 100 },
+///     InputEvent::MouseDown(MouseButton::Left),
+///     InputEvent::MouseUp(MouseButton::Left),
+///     InputEvent::Text("foo".to_string()),
+///     InputEvent::KeyClick(Key::Return),
+/// ]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RecordingDriver {
+    events: Rc<RefCell<Vec<InputEvent>>>,
+}
+
+impl RecordingDriver {
+    /// Creates a new `RecordingDriver` with an empty event log.
+    pub fn new() -> Self {
+        RecordingDriver::default()
+    }
+
+    /// Returns a copy of the recorded events, oldest first.
+    pub fn events(&self) -> Vec<InputEvent> {
+        self.events.borrow().clone()
+    }
+
+    /// Empties the event log.
+    pub fn clear(&self) {
+        self.events.borrow_mut().clear();
+    }
+
+    fn _record(&self, event: InputEvent) -> Result<()> {
+        self.events.borrow_mut().push(event);
+        Ok(())
+    }
+}
+
+impl InputDriver for RecordingDriver {
+    fn mouse_move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self._record(InputEvent::MouseMove { x, y })
+    }
+
+    // position of the last recorded move, (0, 0) before any move
+    fn mouse_location(&self) -> Result<(i32, i32)> {
+        let events = self.events.borrow();
+        let last_move = events.iter().rev().find_map(|event| match event {
+            InputEvent::MouseMove { x, y } => Some((*x, *y)),
+            _ => None,
+        });
+        Ok(last_move.unwrap_or((0, 0)))
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+        self._record(InputEvent::MouseDown(button))
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+        self._record(InputEvent::MouseUp(button))
+    }
+
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self._record(InputEvent::Scroll { dx, dy })
+    }
+
+    fn key_down(&mut self, key: Key) -> Result<()> {
+        self._record(InputEvent::KeyDown(key))
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<()> {
+        self._record(InputEvent::KeyUp(key))
+    }
+
+    fn key_click(&mut self, key: Key) -> Result<()> {
+        self._record(InputEvent::KeyClick(key))
+    }
+
+    fn key_sequence(&mut self, text: &str) -> Result<()> {
+        self._record(InputEvent::Text(text.to_string()))
+    }
+}
@@ -1,13 +1,17 @@
 #![warn(missing_docs)]
 #![doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md"))]
 
-use std::path::Path;
-use std::{thread, time::Duration, time::Instant};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{thread, time::Duration, time::Instant, time::SystemTime, time::UNIX_EPOCH};
 #[allow(unused_imports)]
 use log::{trace, debug, info, warn, error};
 
 use regex::Regex;
 
+use template::{ScaledTemplates, TemplateCache};
+
 use opencv::{
     core::{self},
     prelude::*,
@@ -15,109 +19,59 @@ use opencv::{
     imgproc,
 };
 
-use enigo::*;
+use enigo::{Key, MouseButton};
 
-use core_foundation::base::{CFGetTypeID, CFTypeID, ToVoid};
-use core_foundation::string::{
-    kCFStringEncodingUTF8, CFString, CFStringGetCStringPtr, CFStringGetTypeID,
-};
-use core_foundation::number::{
-    CFBooleanGetTypeID, CFNumberGetTypeID, CFNumberGetValue, CFNumberRef,
-    kCFNumberSInt32Type, kCFNumberSInt64Type, kCFNumberFloat32Type, kCFNumberFloat64Type,
-    CFBooleanGetValue, CFNumberGetType,
-};
-use core_foundation::dictionary::{CFDictionaryGetTypeID};
-use core_graphics::display::*;
-use std::ffi::CStr;
-use std::ops::Deref;
 use std::os::raw::c_void;
 
 pub mod error;
-
+pub mod capture;
+pub mod input;
+pub mod provider;
+pub mod matching;
+pub mod template;
+pub mod query;
+pub mod motion;
+#[cfg(target_os = "macos")]
+pub mod macos;
+#[cfg(all(target_os = "linux", feature = "x11"))]
+pub mod x11;
+
+pub use capture::{Frame, FrameSource, ReplaySource};
+pub use input::{ClickSpec, DragOptions, InputDriver, InputEvent, RecordingDriver, ScrollDirection};
+pub use provider::{WindowProvider, StaticWindowProvider};
+pub use matching::{Match, MatchMethod, MatchOptions, ScaleRange};
+pub use template::{Template, TemplateRef};
+pub use query::WindowQuery;
+pub use motion::MotionProfile;
+#[cfg(target_os = "macos")]
+pub use macos::{CoreGraphicsSource, CoreGraphicsProvider};
+#[cfg(all(target_os = "linux", feature = "x11"))]
+pub use x11::{X11Source, X11Provider, X11Driver};
+
+#[cfg(target_os = "macos")]
 const DEFAULT_HIGH_DPI_RATIO: u32 = 2; // For standard DPI screen: 1, for Retina-like: 2
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_HIGH_DPI_RATIO: u32 = 1;
 const DEFAULT_WAIT_TIME: Duration = Duration::from_millis(90); // delay between mouse move and mouse down and up
 const DEFAULT_CAPTURE_FREQUENCY: f32 = 3.0; // xx captures per second
+const DEFAULT_CHANGE_THRESHOLD: f64 = 0.0; // proportion of changed pixels to exceed for a region to be considered changed
+const SCROLL_LENGTH: i32 = 3; // wheel units per scroll when searching
 
 type Result<T> = std::result::Result<T, error::Error>;
 
-#[derive(Debug)]
-enum DictEntryValue {
-    _Number(i64),
-    _Float(f64),
-    _Bool(bool),
-    _String(String),
-    _DictRef(CFDictionaryRef),
-    _Unknown,
-}
-
 #[derive(Debug)]
 /// The `WindowList` struct represents a list of windows.
 pub struct WindowList(Vec<Window>);
 
 impl WindowList {
-    /// Creates a new `WindowList`.
-    pub fn new() -> WindowList {
-        WindowList(WindowList::_window_list().unwrap())
-    }
-
-    // From https://github.com/sassman/t-rec-rs/blob/39e7560f06055f15dc4078ea1e65db48b135669a/src/macos/window_id.rs
-    // hard nut to crack, some starting point was:
-    // https://stackoverflow.com/questions/60117318/getting-window-owner-names-via-cgwindowlistcopywindowinfo-in-rust
-    // then some more PRs where needed:
-    // https://github.com/servo/core-foundation-rs/pulls?q=is%3Apr+author%3Asassman+
-    fn _window_list() -> Result<Vec<Window>> {
-        let mut win_list: Vec<Window> = vec![];
-        let window_list_info = unsafe {
-            CGWindowListCopyWindowInfo(
-                kCGWindowListOptionIncludingWindow
-                    | kCGWindowListOptionOnScreenOnly
-                    | kCGWindowListExcludeDesktopElements,
-                kCGNullWindowID,
-            )
-        };
-        if window_list_info.is_null() {
-            return Err(error::Error { kind: error::ErrorKind::CoreFoundation, message: "Cannot get window list results from low level C-API call `CGWindowListCopyWindowInfo` -> null".into() });
-        }
-
-        let count = unsafe { CFArrayGetCount(window_list_info) };
-        for i in 0..count {
-            let dic_ref =
-                unsafe { CFArrayGetValueAtIndex(window_list_info, i as isize) as CFDictionaryRef };
-            if dic_ref.is_null() {
-                unsafe {
-                    CFRelease(window_list_info.cast());
-                }
-                return Err(error::Error { kind: error::ErrorKind::CoreFoundation, message: "Cannot get a result from the window list from low level C-API call `CFArrayGetValueAtIndex` -> null".into() });
-            }
-            let window_name = get_from_dict(dic_ref, "kCGWindowName");
-            let window_owner = get_from_dict(dic_ref, "kCGWindowOwnerName");
-            let window_id = get_from_dict(dic_ref, "kCGWindowNumber");
-            let window_bounds = get_from_dict(dic_ref, "kCGWindowBounds");
-            if let (DictEntryValue::_String(win_name), DictEntryValue::_String(win_owner), DictEntryValue::_Number(win_id)) =
-                (window_name, window_owner, window_id)
-            {
-                let mut w = Window{ name: win_name, owner_name: win_owner, id: win_id, bounds: None, capture_frequency: DEFAULT_CAPTURE_FREQUENCY };
-                if let DictEntryValue::_DictRef(b_dic_ref) = window_bounds {
-                    let b_height = get_from_dict(b_dic_ref, "Height");
-                    let b_width = get_from_dict(b_dic_ref, "Width");
-                    let b_x = get_from_dict(b_dic_ref, "X");
-                    let b_y = get_from_dict(b_dic_ref, "Y");
-                    if let (DictEntryValue::_Float(win_height), DictEntryValue::_Float(win_width), DictEntryValue::_Float(win_x), DictEntryValue::_Float(win_y)) =
-                        (b_height, b_width, b_x, b_y)
-                    {
-                        w.bounds = Some(Bounds { x: win_x, y: win_y, width: win_width, height: win_height });
-                        trace!("Window bounds {}, {}, size {} x {}, ", win_x, win_y, win_height, win_width);
-                    }
-                }
-                win_list.push(w);
-            }
-        }
-
-        unsafe {
-            CFRelease(window_list_info.cast());
-        }
+    /// Creates a new `WindowList` from the window provider of the current platform.
+    pub fn new() -> Result<WindowList> {
+        WindowList::from_provider(provider::default_provider().as_ref())
+    }
 
-        Ok(win_list)
+    /// Creates a new `WindowList` of the on-screen windows of the given window provider.
+    pub fn from_provider(provider: &dyn WindowProvider) -> Result<WindowList> {
+        Ok(WindowList(provider.windows()?.into_iter().filter(|w| w.on_screen).collect()))
     }
 
     /// Returns a formatted string representing the list of windows.
@@ -126,7 +80,8 @@ impl WindowList {
         let mut table: String = format!("{:<6} {:<width$} {:<width$}\n", "Id", "Window Name", "Window Owner Name", width = max_width);
         table.push_str(&format!("{}\n","-".repeat(6+max_width*2)));
         for w in &self.0 {
-            let name: String = if w.name.len() > max_width { format!("{}...",&w.name[..max_width-3]) } else { w.name.clone() };
+            // truncate on character boundaries, titles are often not ASCII
+            let name: String = if w.name.chars().count() > max_width { format!("{}...", w.name.chars().take(max_width-3).collect::<String>()) } else { w.name.clone() };
             let owner = w.owner_name.clone();
             table.push_str(&format!("{:<6} {:<width$} {:<width$}\n", w.id, name, owner, width = max_width));
         }
@@ -135,7 +90,8 @@ impl WindowList {
 }
 
 /// Structure representing a rectangle zone in the window.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rect {
     /// Left coordinate of the rectangle, relative to x-axis of the window.
     pub x: u32,
@@ -164,37 +120,179 @@ impl Rect {
     }
 }
 
+/// Enumeration of the zones of a window a search can be restricted to.
+///
+/// Sizes are in pixels of the capture, like the matches (twice the points on a Retina screen).
+///
+/// ```no_run
+/// use std::path::Path;
+/// use macmatic::{Bot, Region};
+///
+/// let toolbar = Region::Top(80);
+/// let mut bot = Bot::new();
+/// bot.set_window_from_name("Untitled").unwrap();
+/// bot.find_in(Path::new("bold.png"), toolbar).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Region {
+    /// The whole window.
+    Full,
+    /// A rectangle of the window.
+    Rect(Rect),
+    /// A band of the given height at the top of the window.
+    Top(u32),
+    /// A band of the given height at the bottom of the window.
+    Bottom(u32),
+    /// A band of the given width on the left of the window.
+    Left(u32),
+    /// A band of the given width on the right of the window.
+    Right(u32),
+}
+
+impl Region {
+    /// Returns the rectangle covered by the region in a window of the given size, clipped to the window.
+    pub fn resolve(&self, width: u32, height: u32) -> Rect {
+        match self {
+            Region::Full => Rect::new(0, 0, width, height),
+            Region::Rect(rect) => {
+                let (x, y) = (rect.x.min(width), rect.y.min(height));
+                Rect::new(x, y, rect.width.min(width - x), rect.height.min(height - y))
+            }
+            Region::Top(h) => Rect::new(0, 0, width, (*h).min(height)),
+            Region::Bottom(h) => Rect::new(0, height - (*h).min(height), width, (*h).min(height)),
+            Region::Left(w) => Rect::new(0, 0, (*w).min(width), height),
+            Region::Right(w) => Rect::new(width - (*w).min(width), 0, (*w).min(width), height),
+        }
+    }
+}
+
+impl From<Rect> for Region {
+    fn from(rect: Rect) -> Self {
+        Region::Rect(rect)
+    }
+}
+
 /// Structure representing the absolute coordinates of a window.
-#[derive(Clone, Debug)]
-#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bounds {
     x: f64,
     y: f64,
     width: f64,
     height: f64,
 }
+
+impl Bounds {
+    /// Creates a new `Bounds`.
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Bounds { x, y, width, height }
+    }
+
+    /// Returns the left coordinate of the window, in screen points.
+    pub fn x(&self) -> f64 {
+        self.x
+    }
+
+    /// Returns the top coordinate of the window, in screen points.
+    pub fn y(&self) -> f64 {
+        self.y
+    }
+
+    /// Returns the width of the window, in screen points.
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// Returns the height of the window, in screen points.
+    pub fn height(&self) -> f64 {
+        self.height
+    }
+}
+
 /// The `Window` struct represents a single window.
+///
+/// With the `serde` feature, the frame source is not serialized.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Window {
     id: i64,
     name: String,
     owner_name: String,
     bounds: Option<Bounds>,
-    capture_frequency: f32
+    #[cfg_attr(feature = "serde", serde(default = "default_on_screen"))]
+    on_screen: bool,
+    #[cfg_attr(feature = "serde", serde(default = "default_capture_frequency"))]
+    capture_frequency: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    frame_source: Option<Arc<dyn FrameSource>>,
+}
+
+#[cfg(feature = "serde")]
+fn default_on_screen() -> bool {
+    true
+}
+
+#[cfg(feature = "serde")]
+fn default_capture_frequency() -> f32 {
+    DEFAULT_CAPTURE_FREQUENCY
 }
 
 impl Window {
+    /// Creates a new `Window`.
+    pub fn new(id: i64, name: &str, owner_name: &str, bounds: Option<Bounds>) -> Self {
+        Window {
+            id,
+            name: name.to_string(),
+            owner_name: owner_name.to_string(),
+            bounds,
+            on_screen: true,
+            capture_frequency: DEFAULT_CAPTURE_FREQUENCY,
+            frame_source: None,
+        }
+    }
+
+    /// Returns the id of the window.
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Returns the name (title) of the window.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name of the owner (application) of the window.
+    pub fn owner_name(&self) -> &str {
+        &self.owner_name
+    }
+
+    /// Returns the bounds of the window, if known.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    /// Returns `true` if the window is visible on screen.
+    pub fn on_screen(&self) -> bool {
+        self.on_screen
+    }
+
+    /// Returns the number of captures per seconds.
+    pub fn capture_frequency(&self) -> f32 {
+        self.capture_frequency
+    }
+
     /// Captures a screenshot of the window and saves it to the specified file.
     pub fn screenshot(&self, file: &Path) -> Result<()> {
-        let (width, height, mut raw_v) = self._capture()?;
-        debug!("w={}, h={}", width, height);
+        let frame = self._capture()?;
+        debug!("w={}, h={}", frame.width(), frame.height());
 
         // Convert to gray CV image
         let mut result = Mat::default();
-        Window::_vec_to_cvmat(&mut raw_v, width as i32, height as i32, &mut result, imgproc::COLOR_BGRA2GRAY)?;
+        Window::_frame_to_cvmat(frame, &mut result, imgproc::COLOR_BGRA2GRAY)?;
 
         // Save file
-        imgcodecs::imwrite(file.to_str().unwrap(), &mut result, &core::Vector::new())?;
+        imgcodecs::imwrite(&file.to_string_lossy(), &mut result, &core::Vector::new())?;
         Ok(())
     }
 
@@ -203,12 +301,28 @@ impl Window {
         self.capture_frequency = value;
     }
 
-    fn _vec_to_cvmat(vec: &mut Vec<u8>, width: i32, height: i32, dest: &mut Mat, color_conv: i32) -> Result<()> {
+    /// Sets whether the window is visible on screen (`true` by default).
+    pub fn set_on_screen(&mut self, value: bool) {
+        self.on_screen = value;
+    }
+
+    /// Sets the source of the captured frames (CoreGraphics by default on macOS, X11 on Linux).
+    pub fn set_frame_source(&mut self, source: Arc<dyn FrameSource>) {
+        self.frame_source = Some(source);
+    }
+
+    fn _frame_to_cvmat(frame: Frame, dest: &mut Mat, color_conv: i32) -> Result<()> {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let mut vec = frame.into_data();
+        // the Mat borrows the buffer, which must hold all the pixels it addresses
+        if vec.len() != width * height * 4 || i32::try_from(width.max(height)).is_err() {
+            return Err(error::Error { kind: error::ErrorKind::CaptureFailed, message: format!("{} bytes cannot hold a {} x {} BGRA image", vec.len(), width, height) });
+        }
         // generate a Mat from raw data image
         let mat = unsafe {
             core::Mat::new_rows_cols_with_data(
-                height,
-                width,
+                height as i32,
+                width as i32,
                 core::CV_8UC4, //u8::typ(),
                 vec.as_mut_ptr() as *mut c_void,
                 core::Mat_AUTO_STEP,
@@ -219,44 +333,201 @@ impl Window {
         Ok(())
     }
 
-    fn _capture(&self) -> Result<(u32, u32, Vec<u8>)> {
-//TODO: check i32 to u32 cast before
-        let img = unsafe {
-            CGDisplay::screenshot(
-                CGRectNull,
-                kCGWindowListOptionIncludingWindow | kCGWindowListExcludeDesktopElements,
-                self.id as u32,
-                kCGWindowImageBestResolution
-                    | kCGWindowImageBoundsIgnoreFraming
-                    | kCGWindowImageShouldBeOpaque,
-            ).unwrap()
-        };
+    // Captures the region of the window, along with the rectangle covered by the region
+    fn _capture_region(&self, region: &Region) -> Result<(Frame, Rect)> {
+        let frame = self._capture()?;
+        let rect = region.resolve(frame.width(), frame.height());
+        if rect.width == 0 || rect.height == 0 {
+            return Err(error::Error { kind: error::ErrorKind::InvalidRegion, message: format!("Region {:?} is outside the window", region) });
+        }
+        match region {
+            Region::Full => Ok((frame, rect)),
+            _ => Ok((frame.crop(&rect), rect)),
+        }
+    }
 
-        let cfdata = img.data();
-        let v = cfdata.bytes().to_vec();
+    fn _capture(&self) -> Result<Frame> {
+        match self.frame_source.as_ref() {
+            Some(source) => source.capture(self.id),
+            #[cfg(target_os = "macos")]
+            None => CoreGraphicsSource.capture(self.id),
+            #[cfg(all(target_os = "linux", feature = "x11"))]
+            None => X11Source::shared()?.capture(self.id),
+            #[cfg(not(any(target_os = "macos", all(target_os = "linux", feature = "x11"))))]
+            None => Err(error::Error { kind: error::ErrorKind::Unsupported, message: format!("No frame source for window id {}", self.id) }),
+        }
+    }
 
-        trace!("img {} x {}", img.width(), img.height());
-        trace!("img bits_per_component {}", img.bits_per_component());
-        trace!("img bits_per_pixel {}", img.bits_per_pixel());
-        trace!("img bytes_per_row {}", img.bytes_per_row());
+    /// Attempts to find the specified template image within the window.
+    ///
+    /// On time-out, the capture holding the best candidate is saved to `snapshot_dir` if set, with the candidate drawn on it.
+    fn find(&self, template: &Template, region: &Region, time_out: Duration, options: &MatchOptions, snapshot_dir: Option<&Path>) -> Result<Match> {
+        let options = &Window::_template_options(template, options)?;
+        trace!("template = {:#?}", template);
+
+        let start = Instant::now();
+        let mut attempts = 0u32;
+        let mut best_score: Option<f64> = None;
+        let mut best_capture: Option<(Mat, Option<Match>)> = None;
+        let mut scaled = ScaledTemplates::new(template, &Window::_coarse_scales(options))?;
+        let found = self._poll(time_out, |index| {
+            attempts = index + 1;
+            // Take screenshot and find the location of the best match
+            let (screenshot, origin) = self._capture_mat(region, options)?;
+            let best = Window::_best_match(&screenshot, &mut scaled, options)?;
+            match best {
+                Some(mut m) if options.accepts(m.score) => {
+                    m.rect.x += origin.x;
+                    m.rect.y += origin.y;
+                    m.frame_index = index;
+                    m.elapsed = start.elapsed();
+                    return Ok(Some(m));
+                }
+                Some(m) => {
+                    let improved = match best_score {
+                        Some(best) => options.is_better(m.score, best),
+                        None => true,
+                    };
+                    if improved {
+                        best_score = Some(m.score);
+                        if snapshot_dir.is_some() {
+                            best_capture = Some((screenshot, Some(m)));
+                        }
+                    }
+                }
+                None => {
+                    warn!("Template {} is larger than the window at every scale", template.id());
+                    if snapshot_dir.is_some() && best_capture.is_none() {
+                        best_capture = Some((screenshot, None));
+                    }
+                }
+            }
+            Ok(None)
+        })?;
+        if let Some(m) = found {
+            return Ok(m);
+        }
+        //return Err(opencv::Error { code: core::StsError, message: "No match found!".to_string() }.into() );
+        let mut not_found = error::NotFound::new(template.id(), best_score, time_out, attempts);
+        if let (Some(dir), Some((screenshot, candidate))) = (snapshot_dir, best_capture) {
+            match Window::_save_snapshot(dir, template, screenshot, candidate.as_ref()) {
+                Ok(file) => not_found.snapshot = Some(file),
+                Err(e) => warn!("Cannot save snapshot: {}", e),
+            }
+        }
+        Err(not_found.into())
+    }
 
-        if img.bytes_per_row() * img.height() != v.len() {
-            panic!("Cannot grab screenshot from CGDisplay of window id {}", self.id);
+    // Saves the capture to the directory, with the candidate and its score drawn on it
+    fn _save_snapshot(dir: &Path, template: &Template, screenshot: Mat, candidate: Option<&Match>) -> Result<PathBuf> {
+        let mut img = if screenshot.channels() == 1 {
+            let mut img = Mat::default();
+            imgproc::cvt_color(&screenshot, &mut img, imgproc::COLOR_GRAY2BGR, 0)?;
+            img
+        } else {
+            screenshot
+        };
+        if let Some(m) = candidate {
+            let red = core::Scalar::new(0.0, 0.0, 255.0, 0.0);
+            let rect = core::Rect::new(m.rect.x as i32, m.rect.y as i32, m.rect.width as i32, m.rect.height as i32);
+            imgproc::rectangle(&mut img, rect, red, 2, imgproc::LINE_8, 0)?;
+            let text_y = if rect.y > 20 { rect.y - 6 } else { rect.y + rect.height + 18 };
+            imgproc::put_text(&mut img, &format!("{:.3}", m.score), core::Point::new(rect.x, text_y), imgproc::FONT_HERSHEY_SIMPLEX, 0.6, red, 2, imgproc::LINE_AA, false)?;
         }
 
-//TODO: check i32 to u32 cast before
-        let bytes_per_pixel = (img.bits_per_pixel() / img.bits_per_component()) as u32;
-        let w = img.bytes_per_row() as u32 / bytes_per_pixel;
-        let h = img.height() as u32;
-        // The bytes per row (also called the “stride”) can be larger than the width of the image.
-        // The extra bytes at the end of each row are simply ignored.
-        // https://stackoverflow.com/a/25706554
+        fs::create_dir_all(dir)?;
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let stem = Path::new(template.id()).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let file = dir.join(format!("{}_{}.png", millis, stem));
+        imgcodecs::imwrite(&file.to_string_lossy(), &img, &core::Vector::new())?;
+        info!("Snapshot saved to {}", file.display());
+        Ok(file)
+    }
 
-        Ok((w, h, v))
+    /// Captures the whole window once and returns the best location of the template, along with the capture.
+    fn find_once(&self, scaled: &mut ScaledTemplates, options: &MatchOptions) -> Result<(Option<Match>, Frame)> {
+        let frame = self._capture()?;
+        let screenshot = Window::_frame_mat(frame.clone(), options)?;
+        Ok((Window::_best_match(&screenshot, scaled, options)?, frame))
     }
 
-    /// Attempts to find the specified template image within the window.
-    fn find(&self, tpl_file: &Path, time_out: Duration) -> Result<Rect> {
+    /// Finds the first of the specified template images to appear within the region of the window.
+    ///
+    /// Each capture is tested against every template, in order. Returns the index of the template found.
+    fn find_any(&self, templates: &[Template], region: &Region, time_out: Duration, options: &MatchOptions) -> Result<(usize, Match)> {
+        let all_options = templates.iter().map(|t| Window::_template_options(t, options)).collect::<Result<Vec<MatchOptions>>>()?;
+        let mut all_scaled = templates.iter().zip(all_options.iter())
+            .map(|(t, o)| ScaledTemplates::new(t, &Window::_coarse_scales(o)))
+            .collect::<Result<Vec<ScaledTemplates>>>()?;
+
+        let start = Instant::now();
+        let mut attempts = 0u32;
+        let found = self._poll(time_out, |index| {
+            attempts = index + 1;
+            let (screenshot, origin) = self._capture_mat(region, options)?;
+            for (i, (scaled, options)) in all_scaled.iter_mut().zip(all_options.iter()).enumerate() {
+                if let Some(mut m) = Window::_best_match(&screenshot, scaled, options)? {
+                    trace!("{}: best score {}", m.template_id, m.score);
+                    if options.accepts(m.score) {
+                        m.rect.x += origin.x;
+                        m.rect.y += origin.y;
+                        m.frame_index = index;
+                        m.elapsed = start.elapsed();
+                        return Ok(Some((i, m)));
+                    }
+                }
+            }
+            Ok(None)
+        })?;
+        let ids: Vec<&str> = templates.iter().map(|t| t.id()).collect();
+        // scores of different templates are not comparable
+        found.ok_or_else(|| error::NotFound::new(&ids.join(", "), None, time_out, attempts).into())
+    }
+
+    /// Waits until the specified template image is no longer visible in the region of the window.
+    fn wait_vanish(&self, template: &Template, region: &Region, time_out: Duration, options: &MatchOptions) -> Result<()> {
+        let options = &Window::_template_options(template, options)?;
+        let mut scaled = ScaledTemplates::new(template, &Window::_coarse_scales(options))?;
+        let vanished = self._poll(time_out, |_| {
+            let (screenshot, _) = self._capture_mat(region, options)?;
+            let visible = Window::_best_match(&screenshot, &mut scaled, options)?.is_some_and(|m| options.accepts(m.score));
+            Ok((!visible).then_some(()))
+        })?;
+        vanished.ok_or_else(|| error::Error { kind: error::ErrorKind::Timeout, message: format!("Template {} still visible after {} ms", template.id(), time_out.as_millis()) })
+    }
+
+    /// Waits until the content of the region of the window changes.
+    ///
+    /// The region is considered changed once the proportion of changed pixels exceeds `threshold`,
+    /// a pixel being changed when a channel differs by more than `capture::PIXEL_TOLERANCE`.
+    fn wait_change(&self, region: &Region, time_out: Duration, threshold: f64) -> Result<()> {
+        let (reference, _) = self._capture_region(region)?;
+        let changed = self._poll(time_out, |_| {
+            let (frame, _) = self._capture_region(region)?;
+            Ok((frame.difference(&reference) > threshold).then_some(()))
+        })?;
+        changed.ok_or_else(|| error::Error { kind: error::ErrorKind::Timeout, message: format!("Region {:?} unchanged after {} ms", region, time_out.as_millis()) })
+    }
+
+    /// Waits until the content of the region of the window has not changed for the quiet period.
+    fn wait_stable(&self, region: &Region, quiet_period: Duration, time_out: Duration, threshold: f64) -> Result<()> {
+        let (mut previous, _) = self._capture_region(region)?;
+        let mut since = Instant::now();
+        let stable = self._poll(time_out, |_| {
+            let (frame, _) = self._capture_region(region)?;
+            if frame.difference(&previous) > threshold {
+                trace!("Region {:?} changed", region);
+                since = Instant::now();
+                previous = frame;
+            }
+            Ok((since.elapsed() >= quiet_period).then_some(()))
+        })?;
+        stable.ok_or_else(|| error::Error { kind: error::ErrorKind::Timeout, message: format!("Region {:?} still changing after {} ms", region, time_out.as_millis()) })
+    }
+
+    // Calls `attempt` once per capture period, with the index of the attempt, until it returns a value.
+    // Returns `None` once the time-out has expired.
+    fn _poll<T>(&self, time_out: Duration, mut attempt: impl FnMut(u32) -> Result<Option<T>>) -> Result<Option<T>> {
         let sleep_d = Duration::from_millis((1f32 / self.capture_frequency * 1000f32) as u64);
         trace!("Sleep time set to {}\"{}", sleep_d.as_secs(), sleep_d.subsec_millis());
 
@@ -264,50 +535,13 @@ impl Window {
             warn!("Time-out is too low ({} ms) for the capture period ({} ms)", time_out.as_millis(), sleep_d.as_millis());
         }
 
-        let cv_template = imgcodecs::imread(&tpl_file.to_str().unwrap(), imgcodecs::IMREAD_GRAYSCALE)?;
-        trace!("template = {:#?}", cv_template);
-//TODO: template caching
-
         let start = Instant::now();
         let mut count = 0u32;
         loop {
             count += 1;
             trace!("Loop n°{}", count);
-
-            // Take screenshot
-            let (width, height, mut raw_v) = self._capture()?;
-            // Convert to gray OpenCV image
-            let mut cv_screenshot = Mat::default();
-            Window::_vec_to_cvmat(&mut raw_v, width as i32, height as i32, &mut cv_screenshot, imgproc::COLOR_BGRA2GRAY)?;
-
-            // Construct the result matrix, a single-channel 32-bit floating-point.
-            // If image is W x H and template is w x h, then result is (W - w + 1) x (H - h + 1)
-            let zero = core::Mat::zeros(
-                width as i32 - cv_template.rows() + 1,
-                height as i32 - cv_template.cols() + 1,
-                core::CV_32FC1,
-            )
-            .unwrap();
-            let mut result = zero.to_mat().unwrap();
-
-            // Optional
-            // Only two matching methods currently accept a mask: TM_SQDIFF and TM_CCORR_NORMED
-            let mask = Mat::default();
-
-            imgproc::match_template(&cv_screenshot, &cv_template, &mut result, imgproc::TM_CCOEFF_NORMED, &mask)?;
-
-            // Find the location of the best match
-            let mut min_val: f64 = 0.0;
-            let mut max_val: f64 = 0.0;
-            let mut min_loc: core::Point = core::Point::new(0,0);
-            let mut max_loc: core::Point = core::Point::new(0,0);
-            core::min_max_loc(&result, Some(&mut min_val), Some(&mut max_val), Some(&mut min_loc), Some(&mut max_loc), &mask)?;
-            let threshold = 0.8; // with TM_SQDIFF_NORMED you could use 0.1
-            if max_val > threshold {
-    //TODO: check i32 to u32 cast before
-                let rect = Rect::new(max_loc.x as u32, max_loc.y as u32, cv_template.cols() as u32, cv_template.rows() as u32); // with TM_SQDIFF_NORMED use min_loc
-
-                return Ok(rect);
+            if let Some(value) = attempt(count - 1)? {
+                return Ok(Some(value));
             }
 
             // loop until time-out
@@ -315,94 +549,132 @@ impl Window {
             let elapsed = start.elapsed();
             if elapsed > time_out {
                 debug!("Timed-out after {}\"{}", elapsed.as_secs(), elapsed.subsec_millis());
-                //return Err(opencv::Error { code: core::StsError, message: "No match found!".to_string() }.into() );
-                return Err(error::Error { kind: error::ErrorKind::ImageNotFound, message: format!("Template {} not found", tpl_file.display()) }.into() );
+                return Ok(None);
             }
         }
     }
-}
 
-fn get_from_dict(dict: CFDictionaryRef, key: &str) -> DictEntryValue {
-    let key: CFString = key.into();
-    let mut value: *const c_void = std::ptr::null();
-    if unsafe { CFDictionaryGetValueIfPresent(dict, key.to_void(), &mut value) != 0 } {
-        let type_id: CFTypeID = unsafe { CFGetTypeID(value) };
-        trace!("key: {:#?} type: {:#?}", key, type_id);
-        if type_id == unsafe { CFNumberGetTypeID() } {
-            let value = value as CFNumberRef;
-            #[allow(non_upper_case_globals)]
-            match unsafe { CFNumberGetType(value) } {
-                kCFNumberSInt64Type => {
-                    trace!("key: {:#?} num type (i64): {:#?}", key, kCFNumberSInt64Type);
-                    let mut value_i64 = 0_i64;
-                    let out_value: *mut i64 = &mut value_i64;
-                    let converted = unsafe { CFNumberGetValue(value, kCFNumberSInt64Type, out_value.cast()) };
-                    if converted {
-                        return DictEntryValue::_Number(value_i64);
-                    }
-                }
-                kCFNumberSInt32Type => {
-                    trace!("key: {:#?} num type (i32): {:#?}", key, kCFNumberSInt32Type);
-                    let mut value_i32 = 0_i32;
-                    let out_value: *mut i32 = &mut value_i32;
-                    let converted = unsafe { CFNumberGetValue(value, kCFNumberSInt32Type, out_value.cast()) };
-                    if converted {
-                        return DictEntryValue::_Number(value_i32 as i64);
-                    }
-                }
-                kCFNumberFloat64Type => {
-                    trace!("key: {:#?} num type (f64): {:#?}", key, kCFNumberFloat64Type);
-                    let mut value_f64 = 0_f64;
-                    let out_value: *mut f64 = &mut value_f64;
-                    let converted = unsafe { CFNumberGetValue(value, kCFNumberFloat64Type, out_value.cast()) };
-                    if converted {
-                        return DictEntryValue::_Float(value_f64);
-                    }
-                }
-                kCFNumberFloat32Type => {
-                    trace!("key: {:#?} num type (f32): {:#?}", key, kCFNumberFloat32Type);
-                    let mut value_f32 = 0_f32;
-                    let out_value: *mut f32 = &mut value_f32;
-                    let converted = unsafe { CFNumberGetValue(value, kCFNumberFloat32Type, out_value.cast()) };
-                    if converted {
-                        return DictEntryValue::_Float(value_f32 as f64);
-                    }
-                }
-                n => {
-                    warn!("Unsupported Number of typeId: {}", n);
-                }
+    /// Finds every occurrence of the specified template image within the window, best score first.
+    fn find_all(&self, template: &Template, region: &Region, options: &MatchOptions) -> Result<Vec<Match>> {
+        let start = Instant::now();
+        let options = &Window::_template_options(template, options)?;
+        let (screenshot, origin) = self._capture_mat(region, options)?;
+        let mut candidates = vec![];
+        for scale in Window::_coarse_scales(options) {
+            let (width, height) = template.scaled_size(scale);
+            if width == 0 || height == 0 {
+                continue;
             }
-        } else if type_id == unsafe { CFBooleanGetTypeID() } {
-            return DictEntryValue::_Bool(unsafe { CFBooleanGetValue(value.cast()) });
-        } else if type_id == unsafe { CFDictionaryGetTypeID() } {
-            return DictEntryValue::_DictRef(value as CFDictionaryRef);
-            //let window_height = get_from_dict(value as CFDictionaryRef, "Height");
-            //trace!("Height={:#?}", window_height);
-        } else if type_id == unsafe { CFStringGetTypeID() } {
-            let c_ptr = unsafe { CFStringGetCStringPtr(value.cast(), kCFStringEncodingUTF8) };
-            return if !c_ptr.is_null() {
-                let c_result = unsafe { CStr::from_ptr(c_ptr) };
-                let result = String::from(c_result.to_str().unwrap());
-                DictEntryValue::_String(result)
-            } else {
-                // in this case there is a high chance we got a `NSString` instead of `CFString`
-                // we have to use the objc runtime to fetch it
-                use objc_foundation::{INSString, NSString};
-                use objc_id::Id;
-                let nss: Id<NSString> = unsafe { Id::from_ptr(value as *mut NSString) };
-                let str = std::str::from_utf8(nss.deref().as_str().as_bytes());
-
-                match str {
-                    Ok(s) => DictEntryValue::_String(s.to_owned()),
-                    Err(_) => DictEntryValue::_Unknown,
-                }
+            let scaled = template.scaled(scale)?;
+            if !Window::_fits(&screenshot, &scaled) {
+                continue;
+            }
+            let result = Window::_match_template(&screenshot, &scaled, options)?;
+            for mut m in matching::peaks(&result, scaled.width(), scaled.height(), options)? {
+                m.scale = scale;
+                candidates.push(m);
+            }
+        }
+        let mut matches = matching::suppress(candidates, options);
+        let elapsed = start.elapsed();
+        for m in matches.iter_mut() {
+            m.template_id = template.id().to_string();
+            m.rect.x += origin.x;
+            m.rect.y += origin.y;
+            m.elapsed = elapsed;
+        }
+        Ok(matches)
+    }
+
+    // Matching options adjusted to the template: a masked template needs a method accepting a mask
+    fn _template_options(template: &Template, options: &MatchOptions) -> Result<MatchOptions> {
+        if template.mask().is_some() { options.masked() } else { Ok(options.clone()) }
+    }
+
+    fn _coarse_scales(options: &MatchOptions) -> Vec<f64> {
+        options.scales.map_or(vec![1.0], |range| range.coarse())
+    }
+
+    // The template must not be empty nor larger than the screenshot
+    fn _fits(screenshot: &Mat, template: &Template) -> bool {
+        template.width() > 0 && template.height() > 0
+            && template.width() <= screenshot.cols() as u32 && template.height() <= screenshot.rows() as u32
+    }
+
+    // Best location of the template, searching the coarse scales then the fine ones around the best coarse scale
+    fn _best_match(screenshot: &Mat, scaled: &mut ScaledTemplates, options: &MatchOptions) -> Result<Option<Match>> {
+        let best = Window::_best_match_at(screenshot, scaled, &Window::_coarse_scales(options), options, None)?;
+        match (options.scales, best.as_ref()) {
+            (Some(range), Some(m)) => {
+                let fine = range.fine(m.scale);
+                Window::_best_match_at(screenshot, scaled, &fine, options, best)
+            }
+            _ => Ok(best),
+        }
+    }
+
+    fn _best_match_at(screenshot: &Mat, templates: &mut ScaledTemplates, scales: &[f64], options: &MatchOptions, mut best: Option<Match>) -> Result<Option<Match>> {
+        for scale in scales {
+            let scaled = match templates.get(*scale)? {
+                Some(scaled) => scaled,
+                None => continue,
             };
-        } else {
-            warn!("Unexpected type: {}", type_id);
+            if !Window::_fits(screenshot, &scaled) {
+                trace!("Skipping scale {}", scale);
+                continue;
+            }
+            let result = Window::_match_template(screenshot, &scaled, options)?;
+
+            let mut min_val: f64 = 0.0;
+            let mut max_val: f64 = 0.0;
+            let mut min_loc: core::Point = core::Point::new(0,0);
+            let mut max_loc: core::Point = core::Point::new(0,0);
+            core::min_max_loc(&result, Some(&mut min_val), Some(&mut max_val), Some(&mut min_loc), Some(&mut max_loc), &Mat::default())?;
+            // with SQDIFF methods, the best match is the lowest value
+            let (score, loc) = if options.method().lower_is_better() { (min_val, min_loc) } else { (max_val, max_loc) };
+            trace!("Best score {} at scale {}", score, scale);
+            let better = match best.as_ref() {
+                Some(b) => options.is_better(score, b.score),
+                None => true,
+            };
+            if better {
+    //TODO: check i32 to u32 cast before
+                let rect = Rect::new(loc.x as u32, loc.y as u32, scaled.width(), scaled.height());
+                let mut m = Match::new(rect, score, templates.template().id());
+                m.scale = *scale;
+                best = Some(m);
+            }
         }
+        Ok(best)
     }
 
-    DictEntryValue::_Unknown
+    // Captures the region of the window as an OpenCV image of the same color space as the templates,
+    // along with the rectangle covered by the region
+    fn _capture_mat(&self, region: &Region, options: &MatchOptions) -> Result<(Mat, Rect)> {
+        let (frame, rect) = self._capture_region(region)?;
+        Ok((Window::_frame_mat(frame, options)?, rect))
+    }
+
+    // Converts the frame to an OpenCV image of the same color space as the templates
+    fn _frame_mat(frame: Frame, options: &MatchOptions) -> Result<Mat> {
+        let color_conv = if options.grayscale { imgproc::COLOR_BGRA2GRAY } else { imgproc::COLOR_BGRA2BGR };
+        let mut cv_screenshot = Mat::default();
+        Window::_frame_to_cvmat(frame, &mut cv_screenshot, color_conv)?;
+        Ok(cv_screenshot)
+    }
+
+    fn _match_template(screenshot: &Mat, template: &Template, options: &MatchOptions) -> Result<Mat> {
+        // The result matrix is a single-channel 32-bit floating-point.
+        // If image is W x H and template is w x h, then result is (W - w + 1) x (H - h + 1)
+        let mut result = Mat::default();
+
+        // Only two matching methods currently accept a mask: TM_SQDIFF and TM_CCORR_NORMED
+        let no_mask = Mat::default();
+        let mask = template.mask().unwrap_or(&no_mask);
+
+        imgproc::match_template(screenshot, template.image(options.grayscale), &mut result, options.method().to_cv(), mask)?;
+        Ok(result)
+    }
 }
 
 #[derive(Debug)]
@@ -410,10 +682,19 @@ fn get_from_dict(dict: CFDictionaryRef, key: &str) -> DictEntryValue {
 pub struct Bot {
     /// The `Window` that the `Bot` interacts with.
     pub window: Option<Window>,
-    controller: Option<Enigo>,
+    controller: Option<Box<dyn InputDriver>>,
     high_dpi_ratio: u32,
     wait_time: Duration,
-    capture_frequency: f32
+    capture_frequency: f32,
+    frame_source: Option<Arc<dyn FrameSource>>,
+    window_provider: Box<dyn WindowProvider>,
+    match_options: MatchOptions,
+    change_threshold: f64,
+    templates: TemplateCache,
+    artifacts_dir: Option<PathBuf>,
+    motion_profile: MotionProfile,
+    motion_count: u64,
+    pointer: Option<(i32, i32)>,
 }
 
 impl Bot {
@@ -424,47 +705,64 @@ impl Bot {
             controller: None,
             high_dpi_ratio: DEFAULT_HIGH_DPI_RATIO,
             wait_time: DEFAULT_WAIT_TIME,
-            capture_frequency: DEFAULT_CAPTURE_FREQUENCY
+            capture_frequency: DEFAULT_CAPTURE_FREQUENCY,
+            frame_source: None,
+            window_provider: provider::default_provider(),
+            match_options: MatchOptions::default(),
+            change_threshold: DEFAULT_CHANGE_THRESHOLD,
+            templates: TemplateCache::default(),
+            artifacts_dir: None,
+            motion_profile: MotionProfile::default(),
+            motion_count: 0,
+            pointer: None,
         }
     }
 
     /// Sets the window based on the specified name.
-    pub fn set_window_from_name(&mut self, name: &str) {
-        for w in WindowList::new().0.iter() {
-            if w.name.eq(name) {
-                let mut nw = w.clone();
-                nw.set_capture_frequency(self.capture_frequency);
-                self.window = Some(nw);
-            }
-        }
+    pub fn set_window_from_name(&mut self, name: &str) -> Result<()> {
+        self._select_window(|w| w.name.eq(name), &format!("no window named {:?}", name))
     }
 
     /// Sets the window based on the specified regex.
-    pub fn set_window_from_regex(&mut self, regex: &str) {
-        let re = Regex::new(regex).unwrap();
-        for w in WindowList::new().0.iter() {
-            if re.is_match(&w.name) {
-                let mut nw = w.clone();
-                nw.set_capture_frequency(self.capture_frequency);
-                self.window = Some(nw);
-            }
-        }
+    pub fn set_window_from_regex(&mut self, regex: &str) -> Result<()> {
+        let re = Regex::new(regex)?;
+        self._select_window(|w| re.is_match(&w.name), &format!("no window name matching {:?}", regex))
     }
 
     /// Sets the window based on the specified id.
-    pub fn set_window_from_id(&mut self, id: i64) {
-        for w in WindowList::new().0.iter() {
-            if w.id == id {
-                let mut nw = w.clone();
-                nw.set_capture_frequency(self.capture_frequency);
-                self.window = Some(nw);
-            }
+    pub fn set_window_from_id(&mut self, id: i64) -> Result<()> {
+        self._select_window(|w| w.id == id, &format!("no window with id {}", id))
+    }
+
+    /// Sets the window matching the specified query.
+    pub fn set_window(&mut self, query: &WindowQuery) -> Result<()> {
+        let windows = self.window_provider.windows()?;
+        let w = query.select(&windows)?;
+        self._use_window(w);
+        Ok(())
+    }
+
+    // Selects the frontmost on-screen window accepted by the predicate
+    fn _select_window(&mut self, predicate: impl Fn(&Window) -> bool, not_found: &str) -> Result<()> {
+        let windows = self.window_provider.windows()?;
+        let w = windows.iter().find(|w| w.on_screen && predicate(w))
+            .ok_or_else(|| error::Error { kind: error::ErrorKind::NoWindow, message: not_found.to_string() })?;
+        self._use_window(w);
+        Ok(())
+    }
+
+    fn _use_window(&mut self, w: &Window) {
+        let mut nw = w.clone();
+        nw.set_capture_frequency(self.capture_frequency);
+        if let Some(source) = self.frame_source.as_ref() {
+            nw.set_frame_source(source.clone());
         }
+        self.window = Some(nw);
     }
 
-    /// Sets the Enigo controller.
-    pub fn set_controller(&mut self, controller: Enigo) {
-        self.controller = Some(controller);
+    /// Sets the input controller (e.g. `Enigo`).
+    pub fn set_controller<D: InputDriver + 'static>(&mut self, controller: D) {
+        self.controller = Some(Box::new(controller));
     }
 
     /// Sets High DPI mode (for standard screen: 1, for Retina-like: 2).
@@ -482,6 +780,49 @@ impl Bot {
         self.capture_frequency = value;
     }
 
+    /// Sets the provider used to look up windows by name, regex or id.
+    pub fn set_window_provider<P: WindowProvider + 'static>(&mut self, provider: P) {
+        self.window_provider = Box::new(provider);
+    }
+
+    /// Sets the source of the captured frames, used by the windows selected afterwards.
+    pub fn set_frame_source<S: FrameSource + 'static>(&mut self, source: S) {
+        let source: Arc<dyn FrameSource> = Arc::new(source);
+        if let Some(window) = self.window.as_mut() {
+            window.set_frame_source(source.clone());
+        }
+        self.frame_source = Some(source);
+    }
+
+    /// Sets the default template matching options.
+    pub fn set_match_options(&mut self, options: MatchOptions) {
+        self.match_options = options;
+    }
+
+    /// Sets the proportion of pixels which must change for a region to be considered changed
+    /// (0.0 by default: any changed pixel).
+    ///
+    /// A pixel is changed when one of its channels differs by more than `capture::PIXEL_TOLERANCE` (16),
+    /// so slight color shifts never count as a change.
+    pub fn set_change_threshold(&mut self, value: f64) {
+        self.change_threshold = value;
+    }
+
+    /// Sets the directory where a snapshot of the window is saved when `click_on_image` times out (none by default).
+    pub fn set_artifacts_dir(&mut self, dir: Option<PathBuf>) {
+        self.artifacts_dir = dir;
+    }
+
+    /// Sets how the mouse pointer moves before a click, a drag or a hover (instant by default).
+    pub fn set_motion_profile(&mut self, profile: MotionProfile) {
+        self.motion_profile = profile;
+    }
+
+    /// Forgets every template loaded from a file, forcing them to be read again.
+    pub fn clear_templates(&mut self) {
+        self.templates.clear();
+    }
+
     /// Waits for the specified duration in milliseconds.
     pub fn sleep(&mut self, millis: u64) {
         thread::sleep(Duration::from_millis(millis));
@@ -489,121 +830,389 @@ impl Bot {
 
     /// Clicks the mouse button at the specified coordinates relative to the window.
     pub fn click(&mut self, relative_x: u32, relative_y: u32) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
-//TODO: check cast or change bounds fields type
-        let window_x = self.window.as_ref().unwrap().bounds.as_ref().unwrap().x as u32;
-        let window_y = self.window.as_ref().unwrap().bounds.as_ref().unwrap().y as u32;
-        let screen_x = relative_x / self.high_dpi_ratio + window_x;
-        trace!("screen x = {} / {} + {}", relative_x, self.high_dpi_ratio, window_x);
-        let screen_y = relative_y / self.high_dpi_ratio + window_y;
-        trace!("screen y = {} / {} + {}", relative_y, self.high_dpi_ratio, window_y);
-        debug!("Click on: {}, {}", screen_x, screen_y);
+        self.click_with(relative_x, relative_y, &ClickSpec::default())
+    }
+
+    /// Clicks at the specified coordinates relative to the window, as described by the `ClickSpec`.
+    pub fn click_with(&mut self, relative_x: u32, relative_y: u32, spec: &ClickSpec) -> Result<()> {
+        let (screen_x, screen_y) = self._screen_point(relative_x, relative_y)?;
+        debug!("Click on: {}, {} ({:?})", screen_x, screen_y, spec);
+        let wait_time = self.wait_time;
 
         // move pointer
-//TODO: check cast
-        controller.mouse_move_to(screen_x as i32, screen_y as i32);
-        thread::sleep(self.wait_time);
+        self._move_pointer(screen_x, screen_y)?;
+        thread::sleep(wait_time);
+
+        let mut pressed = vec![];
+        let mut result = Ok(());
+        for key in spec.modifiers.iter() {
+            result = self.key_down(*key);
+            if result.is_err() {
+                break;
+            }
+            pressed.push(*key);
+            thread::sleep(wait_time);
+        }
         // click
-        controller.mouse_down(MouseButton::Left);
-        thread::sleep(self.wait_time);
-        controller.mouse_up(MouseButton::Left);
+        if result.is_ok() {
+            result = self._clicks(spec, wait_time);
+        }
+        // release the modifiers even if the click failed
+        for key in pressed.into_iter().rev() {
+            thread::sleep(wait_time);
+            let released = self.key_up(key);
+            if result.is_ok() {
+                result = released;
+            }
+        }
+        result
+    }
+
+    fn _clicks(&mut self, spec: &ClickSpec, wait_time: Duration) -> Result<()> {
+        let controller = self._controller()?;
+        for i in 0..spec.count {
+            if i > 0 {
+                thread::sleep(wait_time);
+            }
+            controller.mouse_down(spec.button)?;
+            thread::sleep(wait_time);
+            controller.mouse_up(spec.button)?;
+        }
         Ok(())
     }
 
     /// Pushes down the mouse button at the specified coordinates relative to the window.
     pub fn mouse_down_on(&mut self, relative_x: u32, relative_y: u32) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
-//TODO: check cast or change bounds fields type
-        let window_x = self.window.as_ref().unwrap().bounds.as_ref().unwrap().x as u32;
-        let window_y = self.window.as_ref().unwrap().bounds.as_ref().unwrap().y as u32;
-        let screen_x = relative_x / self.high_dpi_ratio + window_x;
-        trace!("screen x = {} / {} + {}", relative_x, self.high_dpi_ratio, window_x);
-        let screen_y = relative_y / self.high_dpi_ratio + window_y;
-        trace!("screen y = {} / {} + {}", relative_y, self.high_dpi_ratio, window_y);
+        let (screen_x, screen_y) = self._screen_point(relative_x, relative_y)?;
         debug!("Mouse down on: {}, {}", screen_x, screen_y);
-
-//TODO: check cast
-        controller.mouse_move_to(screen_x as i32, screen_y as i32);
+        self._move_pointer(screen_x, screen_y)?;
         thread::sleep(self.wait_time);
-        controller.mouse_down(MouseButton::Left);
+        self._controller()?.mouse_down(MouseButton::Left)?;
         Ok(())
     }
 
     /// Releases the mouse button at the specified coordinates relative to the window.
     pub fn mouse_up_on(&mut self, relative_x: u32, relative_y: u32) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
-//TODO: check cast or change bounds fields type
-        let window_x = self.window.as_ref().unwrap().bounds.as_ref().unwrap().x as u32;
-        let window_y = self.window.as_ref().unwrap().bounds.as_ref().unwrap().y as u32;
-        let screen_x = relative_x / self.high_dpi_ratio + window_x;
-        trace!("screen x = {} / {} + {}", relative_x, self.high_dpi_ratio, window_x);
-        let screen_y = relative_y / self.high_dpi_ratio + window_y;
-        trace!("screen y = {} / {} + {}", relative_y, self.high_dpi_ratio, window_y);
+        let (screen_x, screen_y) = self._screen_point(relative_x, relative_y)?;
         debug!("Mouse up on: {}, {}", screen_x, screen_y);
+        self._move_pointer(screen_x, screen_y)?;
+        thread::sleep(self.wait_time);
+        self._controller()?.mouse_up(MouseButton::Left)?;
+        Ok(())
+    }
+
+    /// Moves the mouse pointer to the specified coordinates relative to the window, without clicking.
+    pub fn move_to(&mut self, relative_x: u32, relative_y: u32) -> Result<()> {
+        let (screen_x, screen_y) = self._screen_point(relative_x, relative_y)?;
+        debug!("Move to: {}, {}", screen_x, screen_y);
+        self._move_pointer(screen_x, screen_y)
+    }
 
-//TODO: check cast
-        controller.mouse_move_to(screen_x as i32, screen_y as i32);
+    /// Moves the mouse pointer to the specified coordinates relative to the window and stays there for `dwell`,
+    /// to trigger tooltips and hover menus.
+    pub fn hover(&mut self, relative_x: u32, relative_y: u32, dwell: Duration) -> Result<()> {
+        self.move_to(relative_x, relative_y)?;
+        thread::sleep(dwell);
+        Ok(())
+    }
+
+    /// Searches for a specified image within the window and hovers its center for `dwell`.
+    pub fn hover_image<'a>(&mut self, template: impl Into<TemplateRef<'a>>, dwell: Duration) -> Result<(u32, u32)> {
+        let (x, y) = self.find(template)?.center();
+        self.hover(x, y, dwell)?;
+        Ok((x, y))
+    }
+
+    /// Drags with the left mouse button from a point to another, both relative to the window.
+    ///
+    /// The pointer goes through intermediate positions, as many applications ignore a jump to the target.
+    pub fn drag(&mut self, from: (u32, u32), to: (u32, u32), options: &DragOptions) -> Result<()> {
+        let start = self._screen_point(from.0, from.1)?;
+        let end = self._screen_point(to.0, to.1)?;
+        debug!("Drag from {:?} to {:?}", start, end);
+
+        self._move_pointer(start.0, start.1)?;
+        thread::sleep(self.wait_time);
+        self._controller()?.mouse_down(MouseButton::Left)?;
+        thread::sleep(options.hold_before_move);
+        let profile = self.motion_profile.with_timing(options.steps, options.duration);
+        let moved = self._move_along(&profile, start, end);
         thread::sleep(self.wait_time);
-        controller.mouse_up(MouseButton::Left);
+        // release the button even if a move failed
+        let released = self._controller()?.mouse_up(MouseButton::Left);
+        moved.and(released)
+    }
+
+    /// Drags the center of an image to the center of another one, both searched within the window.
+    pub fn drag_image_to_image<'a, 'b>(&mut self, source: impl Into<TemplateRef<'a>>, target: impl Into<TemplateRef<'b>>, options: &DragOptions) -> Result<()> {
+        let from = self.find(source)?.center();
+        let to = self.find(target)?.center();
+        self.drag(from, to, options)
+    }
+
+    // Moves the pointer to the screen coordinates, following the motion profile
+    fn _move_pointer(&mut self, screen_x: i32, screen_y: i32) -> Result<()> {
+        let to = (screen_x, screen_y);
+        // the user may have moved the pointer since the last motion, otherwise it jumps if its position is unknown
+        let from = match self._controller()?.mouse_location() {
+            Ok(location) => location,
+            Err(_) => self.pointer.unwrap_or(to),
+        };
+        let profile = self.motion_profile;
+        self._move_along(&profile, from, to)
+    }
+
+    fn _move_along(&mut self, profile: &MotionProfile, from: (i32, i32), to: (i32, i32)) -> Result<()> {
+        let points = motion::path(profile, from, to, self.motion_count);
+        self.motion_count += 1;
+        let pause = profile.duration() / points.len() as u32;
+        trace!("Moving from {:?} to {:?} in {} steps", from, to, points.len());
+        let controller = self._controller()?;
+        for (x, y) in points {
+            if !pause.is_zero() {
+                thread::sleep(pause);
+            }
+            controller.mouse_move_to(x, y)?;
+        }
+        self.pointer = Some(to);
         Ok(())
     }
 
+    /// Scrolls the wheel over the center of the window, positive values scrolling right and down.
+    pub fn scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let bounds = self._bounds()?;
+//TODO: check cast or change bounds fields type
+        let (screen_x, screen_y) = ((bounds.x + bounds.width / 2.0) as i32, (bounds.y + bounds.height / 2.0) as i32);
+        self._scroll(screen_x, screen_y, dx, dy)
+    }
+
+    /// Scrolls the wheel over the specified coordinates relative to the window.
+    pub fn scroll_at(&mut self, relative_x: u32, relative_y: u32, dx: i32, dy: i32) -> Result<()> {
+        let (screen_x, screen_y) = self._screen_point(relative_x, relative_y)?;
+        self._scroll(screen_x, screen_y, dx, dy)
+    }
+
+    /// Scrolls the window in the given direction until a specified image shows up.
+    ///
+    /// Gives up after `max_scrolls` scrolls, or as soon as a scroll does not change the content anymore,
+    /// with an `ErrorKind::ImageNotFound` error telling which limit stopped the search.
+    pub fn scroll_until_found<'a>(&mut self, template: impl Into<TemplateRef<'a>>, direction: ScrollDirection, max_scrolls: u32) -> Result<Match> {
+        let template = self.templates.resolve(template.into())?;
+        let options = Window::_template_options(&template, &self.match_options)?;
+        let mut scaled = ScaledTemplates::new(&template, &Window::_coarse_scales(&options))?;
+        let (dx, dy) = direction.amounts(SCROLL_LENGTH);
+        let start = Instant::now();
+        let mut previous: Option<Frame> = None;
+        let mut best_score: Option<f64> = None;
+        let mut searches = 0u32;
+        loop {
+            searches += 1;
+            // the same capture is searched and compared with the previous one
+            let (best, frame) = self._window()?.find_once(&mut scaled, &options)?;
+            match best {
+                Some(mut m) if options.accepts(m.score) => {
+                    debug!("found after {} scrolls: {:?} (score {}, scale {})", searches - 1, m.rect, m.score, m.scale);
+                    m.frame_index = searches - 1;
+                    m.elapsed = start.elapsed();
+                    return Ok(m);
+                }
+                Some(m) if best_score.is_some_and(|best| !options.is_better(m.score, best)) => (),
+                Some(m) => best_score = Some(m.score),
+                None => (),
+            }
+            let stop = if searches > max_scrolls {
+                Some("scroll limit reached")
+            } else if previous.as_ref().is_some_and(|p| frame.difference(p) <= self.change_threshold) {
+                Some("content does not scroll anymore")
+            } else {
+                None
+            };
+            if let Some(reason) = stop {
+                debug!("Stopping: {}", reason);
+                // the search is limited by scrolls, not by time
+                let not_found = error::NotFound::new(template.id(), best_score, Duration::ZERO, searches);
+                let best_score = best_score.map_or("none".to_string(), |score| format!("{:.3}", score));
+                return Err(error::Error {
+                    message: format!("Template {} not found after {} scrolls (best score {}, {})", template.id(), searches - 1, best_score, reason),
+                    kind: error::ErrorKind::ImageNotFound(Box::new(not_found)),
+                });
+            }
+            previous = Some(frame);
+            self.scroll(dx, dy)?;
+            thread::sleep(self.wait_time);
+        }
+    }
+
+    fn _scroll(&mut self, screen_x: i32, screen_y: i32, dx: i32, dy: i32) -> Result<()> {
+        debug!("Scroll {}, {} on: {}, {}", dx, dy, screen_x, screen_y);
+        self._move_pointer(screen_x, screen_y)?;
+        thread::sleep(self.wait_time);
+        self._controller()?.mouse_scroll(dx, dy)
+    }
+
     /// Clicks at the top of the bottom to activate the window.
     pub fn activate_window(&mut self) -> Result<()> {
         // click on the middle of the title bar to activate the window
         debug!("Activating window");
 //TODO: check cast or change bounds fields type
-        let window_width = self.window.as_ref().unwrap().bounds.as_ref().unwrap().width as u32;
+        let window_width = self._bounds()?.width as u32;
         self.click(window_width, 20)?;
         Ok(())
     }
 
     /// Searches for a a specified image within the window and returns the `Rect` coordinates.
-    pub fn find(&mut self, template: &Path) -> Result<Rect> {
-        let rect = self.window.as_ref().unwrap().find(template, Duration::ZERO)?;
-        debug!("found: {:?}", rect);
-        Ok(rect)
+    pub fn find<'a>(&mut self, template: impl Into<TemplateRef<'a>>) -> Result<Rect> {
+        let options = self.match_options.clone();
+        self.find_with(template, &options)
+    }
+
+    /// Searches for a specified image within the window with the given matching options.
+    pub fn find_with<'a>(&mut self, template: impl Into<TemplateRef<'a>>, options: &MatchOptions) -> Result<Rect> {
+        Ok(self.find_match_with(template, Duration::ZERO, options)?.rect)
+    }
+
+    /// Searches for a specified image within the window until time-out and returns the `Match` details.
+    pub fn find_match<'a>(&mut self, template: impl Into<TemplateRef<'a>>, time_out: Duration) -> Result<Match> {
+        let options = self.match_options.clone();
+        self.find_match_with(template, time_out, &options)
+    }
+
+    /// Searches for a specified image within the window with the given matching options and returns the `Match` details.
+    pub fn find_match_with<'a>(&mut self, template: impl Into<TemplateRef<'a>>, time_out: Duration, options: &MatchOptions) -> Result<Match> {
+        let template = self.templates.resolve(template.into())?;
+        let m = self._window()?.find(&template, &Region::Full, time_out, options, None)?;
+        debug!("found: {:?} (score {}, scale {})", m.rect, m.score, m.scale);
+        Ok(m)
+    }
+
+    /// Searches for every occurrence of a specified image within the window, best score first.
+    ///
+    /// Use `matching::sort_by_reading_order` to sort them from top left to bottom right instead.
+    pub fn find_all<'a>(&mut self, template: impl Into<TemplateRef<'a>>) -> Result<Vec<Match>> {
+        let options = self.match_options.clone();
+        self.find_all_with(template, &options)
+    }
+
+    /// Searches for every occurrence of a specified image within the window with the given matching options.
+    pub fn find_all_with<'a>(&mut self, template: impl Into<TemplateRef<'a>>, options: &MatchOptions) -> Result<Vec<Match>> {
+        let template = self.templates.resolve(template.into())?;
+        let matches = self._window()?.find_all(&template, &Region::Full, options)?;
+        debug!("found {} matches", matches.len());
+        Ok(matches)
+    }
+
+    /// Searches for a specified image within a region of the window.
+    ///
+    /// Fails with `ErrorKind::InvalidRegion` if the region does not overlap the window.
+    pub fn find_in<'a>(&mut self, template: impl Into<TemplateRef<'a>>, region: impl Into<Region>) -> Result<Rect> {
+        let options = self.match_options.clone();
+        self.find_in_with(template, region, &options)
+    }
+
+    /// Searches for a specified image within a region of the window with the given matching options.
+    pub fn find_in_with<'a>(&mut self, template: impl Into<TemplateRef<'a>>, region: impl Into<Region>, options: &MatchOptions) -> Result<Rect> {
+        let template = self.templates.resolve(template.into())?;
+        let m = self._window()?.find(&template, &region.into(), Duration::ZERO, options, None)?;
+        debug!("found: {:?} (score {}, scale {})", m.rect, m.score, m.scale);
+        Ok(m.rect)
+    }
+
+    /// Searches for every occurrence of a specified image within a region of the window, best score first.
+    pub fn find_all_in<'a>(&mut self, template: impl Into<TemplateRef<'a>>, region: impl Into<Region>) -> Result<Vec<Match>> {
+        let template = self.templates.resolve(template.into())?;
+        let options = self.match_options.clone();
+        let matches = self._window()?.find_all(&template, &region.into(), &options)?;
+        debug!("found {} matches", matches.len());
+        Ok(matches)
+    }
+
+    /// Waits until one of the specified images appears in the window.
+    ///
+    /// Returns the index of the image found, along with the `Match` details.
+    pub fn wait_any<'a, T: Into<TemplateRef<'a>> + Copy>(&mut self, templates: &[T], time_out: Duration) -> Result<(usize, Match)> {
+        let mut resolved = vec![];
+        for template in templates {
+            resolved.push(self.templates.resolve((*template).into())?);
+        }
+        let options = self.match_options.clone();
+        let (index, m) = self._window()?.find_any(&resolved, &Region::Full, time_out, &options)?;
+        debug!("found #{} {}: {:?} (score {}, scale {})", index, m.template_id, m.rect, m.score, m.scale);
+        Ok((index, m))
+    }
+
+    /// Waits until a specified image is no longer visible in the window.
+    pub fn wait_vanish<'a>(&mut self, template: impl Into<TemplateRef<'a>>, time_out: Duration) -> Result<()> {
+        let template = self.templates.resolve(template.into())?;
+        let options = self.match_options.clone();
+        debug!("Waiting for {} to vanish", template.id());
+        self._window()?.wait_vanish(&template, &Region::Full, time_out, &options)
+    }
+
+    /// Waits until the content of a region of the window changes (see `set_change_threshold`).
+    ///
+    /// Channel differences up to `capture::PIXEL_TOLERANCE` are ignored.
+    pub fn wait_change(&mut self, region: impl Into<Region>, time_out: Duration) -> Result<()> {
+        let region = region.into();
+        debug!("Waiting for {:?} to change", region);
+        let threshold = self.change_threshold;
+        self._window()?.wait_change(&region, time_out, threshold)
+    }
+
+    /// Waits until the content of a region of the window has not changed for `quiet_period`.
+    pub fn wait_stable(&mut self, region: impl Into<Region>, quiet_period: Duration, time_out: Duration) -> Result<()> {
+        let region = region.into();
+        debug!("Waiting for {:?} to be stable", region);
+        let threshold = self.change_threshold;
+        self._window()?.wait_stable(&region, quiet_period, time_out, threshold)
     }
 
     /// Searches for a specified image within the window and clicks at its center.
-    pub fn click_on_image(&mut self, template: &Path, time_out: u64) -> Result<(u32, u32)> {
-        debug!("Searching {}", template.display());
-        let rect = self.window.as_ref().unwrap().find(template, Duration::from_millis(time_out))?;
-        debug!("Image found on: {:?}", rect);
-        let (x, y) = rect.center();
+    pub fn click_on_image<'a>(&mut self, template: impl Into<TemplateRef<'a>>, time_out: u64) -> Result<(u32, u32)> {
+        let options = self.match_options.clone();
+        self.click_on_image_with(template, time_out, &options)
+    }
+
+    /// Searches for a specified image within the window with the given matching options and clicks at its center.
+    pub fn click_on_image_with<'a>(&mut self, template: impl Into<TemplateRef<'a>>, time_out: u64, options: &MatchOptions) -> Result<(u32, u32)> {
+        let template = self.templates.resolve(template.into())?;
+        debug!("Searching {}", template.id());
+        let snapshot_dir = self.artifacts_dir.clone();
+        let m = self._window()?.find(&template, &Region::Full, Duration::from_millis(time_out), options, snapshot_dir.as_deref())?;
+        debug!("Image found on: {:?} (score {}, scale {})", m.rect, m.score, m.scale);
+        let (x, y) = m.rect.center();
         self.click(x, y)?;
         Ok((x, y))
     }
 
     /// Presses down the given key.
     pub fn key_down(&mut self, key: Key) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
+        let controller = self._controller()?;
         debug!("Key down: {:#?}", key);
-        controller.key_down(key);
+        controller.key_down(key)?;
         Ok(())
     }
 
     /// Releases the given key.
     pub fn key_up(&mut self, key: Key) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
+        let controller = self._controller()?;
         debug!("Key up: {:#?}", key);
-        controller.key_up(key);
+        controller.key_up(key)?;
         Ok(())
     }
 
     /// Presses and release the key.
     pub fn key_click(&mut self, key: Key) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
+        let controller = self._controller()?;
         debug!("Key click: {:#?}", key);
-        controller.key_click(key);
+        controller.key_click(key)?;
         Ok(())
     }
 
     /// Types a string.
     pub fn key_sequence(&mut self, text: &str) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
+        let controller = self._controller()?;
         debug!("Typing: {}", text);
-        controller.key_sequence(text);
+        controller.key_sequence(text)?;
         Ok(())
     }
 
@@ -614,11 +1223,303 @@ impl Bot {
 
     /// Types a string followed by return.
     pub fn writeln(&mut self, text: &str) -> Result<()> {
-        let controller = self.controller.as_mut().unwrap();
+        let controller = self._controller()?;
         debug!("Typing: {}", text);
-        controller.key_sequence(text);
+        controller.key_sequence(text)?;
         debug!("Pressing enter");
-        controller.key_click(Key::Return);
+        controller.key_click(Key::Return)?;
         Ok(())
     }
+
+    fn _window(&self) -> Result<&Window> {
+        self.window.as_ref().ok_or_else(|| error::Error { kind: error::ErrorKind::NoWindow, message: "no window selected".into() })
+    }
+
+    fn _bounds(&self) -> Result<&Bounds> {
+        let window = self._window()?;
+        window.bounds.as_ref().ok_or_else(|| error::Error { kind: error::ErrorKind::NoBounds, message: format!("unknown bounds for window id {}", window.id) })
+    }
+
+    fn _controller(&mut self) -> Result<&mut Box<dyn InputDriver>> {
+        self.controller.as_mut().ok_or_else(|| error::Error { kind: error::ErrorKind::NoController, message: "no input controller set".into() })
+    }
+
+    // Converts coordinates relative to the window, in pixels of the capture, to screen coordinates
+    fn _screen_point(&self, relative_x: u32, relative_y: u32) -> Result<(i32, i32)> {
+        let bounds = self._bounds()?;
+//TODO: check cast or change bounds fields type
+        let (window_x, window_y) = (bounds.x as i32, bounds.y as i32);
+        let screen_x = (relative_x / self.high_dpi_ratio) as i32 + window_x;
+        trace!("screen x = {} / {} + {}", relative_x, self.high_dpi_ratio, window_x);
+        let screen_y = (relative_y / self.high_dpi_ratio) as i32 + window_y;
+        trace!("screen y = {} / {} + {}", relative_y, self.high_dpi_ratio, window_y);
+        Ok((screen_x, screen_y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Window>();
+        assert_send_sync::<ReplaySource>();
+    }
+
+    #[test]
+    fn motion_starts_from_pointer_location() {
+        use input::{InputEvent, RecordingDriver};
+
+        let mut recorder = RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        bot.set_motion_profile(MotionProfile::Linear { steps: 2, duration: Duration::ZERO });
+
+        bot.move_to(0, 0).unwrap();
+        recorder.clear();
+        // the user moves the pointer away
+        recorder.mouse_move_to(300, 250).unwrap();
+        bot.move_to(100, 100).unwrap();
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 300, y: 250 },
+            InputEvent::MouseMove { x: 250, y: 200 },
+            InputEvent::MouseMove { x: 200, y: 150 },
+        ]);
+    }
+
+    #[test]
+    fn move_and_hover_convert_window_points_to_screen() {
+        use input::InputEvent;
+
+        let recorder = input::RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        bot.move_to(30, 40).unwrap();
+        // window pixels are halved into screen points on high DPI displays
+        bot.set_high_dpi_ratio(2);
+        bot.move_to(200, 100).unwrap();
+        bot.hover(61, 21, Duration::ZERO).unwrap();
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 130, y: 90 },
+            InputEvent::MouseMove { x: 200, y: 100 },
+            InputEvent::MouseMove { x: 130, y: 60 },
+        ]);
+    }
+
+    #[test]
+    fn prettify_truncates_multibyte_names() {
+        let name = format!("Café {}", "é".repeat(40));
+        let list = WindowList(vec![Window::new(7, &name, "Preview", None)]);
+        let table = list.prettify();
+        let row = table.lines().nth(2).unwrap();
+        assert!(row.starts_with(&format!("{:<6} {}...", 7, name.chars().take(27).collect::<String>())));
+    }
+
+    // A bot clicking through the recorder in a window at (100, 50), without high DPI scaling nor waits
+    fn recording_bot(recorder: &input::RecordingDriver) -> Bot {
+        let mut bot = Bot::new();
+        bot.set_controller(recorder.clone());
+        bot.set_high_dpi_ratio(1);
+        bot.set_wait_time(Duration::ZERO);
+        bot.window = Some(Window::new(1, "Untitled", "TextEdit", Some(Bounds::new(100.0, 50.0, 800.0, 600.0))));
+        bot
+    }
+
+    // A driver recording events, except the ones the predicate fails given the events recorded so far
+    #[derive(Debug)]
+    struct FailingDriver {
+        recorder: input::RecordingDriver,
+        fails: fn(&[input::InputEvent], &input::InputEvent) -> bool,
+    }
+
+    impl FailingDriver {
+        fn _check(&self, event: input::InputEvent) -> Result<()> {
+            if (self.fails)(&self.recorder.events(), &event) {
+                return Err(error::Error { kind: error::ErrorKind::Unsupported, message: format!("Failing on {:?}", event) });
+            }
+            Ok(())
+        }
+    }
+
+    impl InputDriver for FailingDriver {
+        fn mouse_move_to(&mut self, x: i32, y: i32) -> Result<()> {
+            self._check(input::InputEvent::MouseMove { x, y })?;
+            self.recorder.mouse_move_to(x, y)
+        }
+
+        fn mouse_location(&self) -> Result<(i32, i32)> {
+            self.recorder.mouse_location()
+        }
+
+        fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+            self._check(input::InputEvent::MouseDown(button))?;
+            self.recorder.mouse_down(button)
+        }
+
+        fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+            self._check(input::InputEvent::MouseUp(button))?;
+            self.recorder.mouse_up(button)
+        }
+
+        fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+            self._check(input::InputEvent::Scroll { dx, dy })?;
+            self.recorder.mouse_scroll(dx, dy)
+        }
+
+        fn key_down(&mut self, key: Key) -> Result<()> {
+            self._check(input::InputEvent::KeyDown(key))?;
+            self.recorder.key_down(key)
+        }
+
+        fn key_up(&mut self, key: Key) -> Result<()> {
+            self._check(input::InputEvent::KeyUp(key))?;
+            self.recorder.key_up(key)
+        }
+
+        fn key_click(&mut self, key: Key) -> Result<()> {
+            self._check(input::InputEvent::KeyClick(key))?;
+            self.recorder.key_click(key)
+        }
+
+        fn key_sequence(&mut self, text: &str) -> Result<()> {
+            self._check(input::InputEvent::Text(text.to_string()))?;
+            self.recorder.key_sequence(text)
+        }
+    }
+
+    #[test]
+    fn click_holds_modifiers_around_the_click() {
+        use input::InputEvent;
+
+        let recorder = input::RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        let spec = ClickSpec::right().with_modifier(Key::Shift).with_modifier(Key::Control);
+        bot.click_with(10, 20, &spec).unwrap();
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 110, y: 70 },
+            InputEvent::KeyDown(Key::Shift),
+            InputEvent::KeyDown(Key::Control),
+            InputEvent::MouseDown(MouseButton::Right),
+            InputEvent::MouseUp(MouseButton::Right),
+            InputEvent::KeyUp(Key::Control),
+            InputEvent::KeyUp(Key::Shift),
+        ]);
+    }
+
+    #[test]
+    fn double_click_clicks_twice() {
+        use input::InputEvent;
+
+        let recorder = input::RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        bot.click_with(10, 20, &ClickSpec::double()).unwrap();
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 110, y: 70 },
+            InputEvent::MouseDown(MouseButton::Left),
+            InputEvent::MouseUp(MouseButton::Left),
+            InputEvent::MouseDown(MouseButton::Left),
+            InputEvent::MouseUp(MouseButton::Left),
+        ]);
+    }
+
+    #[test]
+    fn failed_click_releases_modifiers() {
+        use input::InputEvent;
+
+        let recorder = input::RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        bot.set_controller(FailingDriver { recorder: recorder.clone(), fails: |_, event| matches!(event, InputEvent::MouseDown(_)) });
+        let spec = ClickSpec::default().with_modifier(Key::Shift).with_modifier(Key::Control);
+        assert!(bot.click_with(10, 20, &spec).is_err());
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 110, y: 70 },
+            InputEvent::KeyDown(Key::Shift),
+            InputEvent::KeyDown(Key::Control),
+            InputEvent::KeyUp(Key::Control),
+            InputEvent::KeyUp(Key::Shift),
+        ]);
+    }
+
+    #[test]
+    fn drag_moves_with_the_button_down() {
+        use input::InputEvent;
+
+        let recorder = input::RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        let options = DragOptions { steps: 4, duration: Duration::ZERO, hold_before_move: Duration::ZERO };
+        bot.drag((10, 20), (50, 60), &options).unwrap();
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 110, y: 70 },
+            InputEvent::MouseDown(MouseButton::Left),
+            InputEvent::MouseMove { x: 120, y: 80 },
+            InputEvent::MouseMove { x: 130, y: 90 },
+            InputEvent::MouseMove { x: 140, y: 100 },
+            InputEvent::MouseMove { x: 150, y: 110 },
+            InputEvent::MouseUp(MouseButton::Left),
+        ]);
+    }
+
+    #[test]
+    fn failed_drag_releases_the_button() {
+        use input::InputEvent;
+
+        let recorder = input::RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        bot.set_controller(FailingDriver {
+            recorder: recorder.clone(),
+            fails: |events, event| matches!(event, InputEvent::MouseMove { .. }) && events.contains(&InputEvent::MouseDown(MouseButton::Left)),
+        });
+        let options = DragOptions { steps: 4, duration: Duration::ZERO, hold_before_move: Duration::ZERO };
+        assert!(bot.drag((10, 20), (50, 60), &options).is_err());
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 110, y: 70 },
+            InputEvent::MouseDown(MouseButton::Left),
+            InputEvent::MouseUp(MouseButton::Left),
+        ]);
+    }
+
+    #[test]
+    fn scroll_over_the_window() {
+        use input::InputEvent;
+
+        let recorder = input::RecordingDriver::new();
+        let mut bot = recording_bot(&recorder);
+        let (dx, dy) = ScrollDirection::Down.amounts(3);
+        bot.scroll(dx, dy).unwrap();
+        let (dx, dy) = ScrollDirection::Left.amounts(3);
+        bot.scroll_at(10, 20, dx, dy).unwrap();
+        assert_eq!(recorder.events(), vec![
+            InputEvent::MouseMove { x: 500, y: 350 },
+            InputEvent::Scroll { dx: 0, dy: 3 },
+            InputEvent::MouseMove { x: 110, y: 70 },
+            InputEvent::Scroll { dx: -3, dy: 0 },
+        ]);
+        assert_eq!(ScrollDirection::Up.amounts(3), (0, -3));
+        assert_eq!(ScrollDirection::Right.amounts(3), (3, 0));
+    }
+
+    #[test]
+    fn region_bands() {
+        assert_eq!(Region::Full.resolve(200, 100), Rect::new(0, 0, 200, 100));
+        assert_eq!(Region::Top(30).resolve(200, 100), Rect::new(0, 0, 200, 30));
+        assert_eq!(Region::Bottom(30).resolve(200, 100), Rect::new(0, 70, 200, 30));
+        assert_eq!(Region::Left(50).resolve(200, 100), Rect::new(0, 0, 50, 100));
+        assert_eq!(Region::Right(50).resolve(200, 100), Rect::new(150, 0, 50, 100));
+    }
+
+    #[test]
+    fn region_larger_than_window() {
+        assert_eq!(Region::Top(300).resolve(200, 100), Rect::new(0, 0, 200, 100));
+        assert_eq!(Region::Bottom(300).resolve(200, 100), Rect::new(0, 0, 200, 100));
+        assert_eq!(Region::Right(300).resolve(200, 100), Rect::new(0, 0, 200, 100));
+        assert_eq!(Region::Rect(Rect::new(150, 80, 100, 100)).resolve(200, 100), Rect::new(150, 80, 50, 20));
+    }
+
+    #[test]
+    fn region_outside_window_is_empty() {
+        let rect = Region::Rect(Rect::new(250, 120, 10, 10)).resolve(200, 100);
+        assert_eq!((rect.width, rect.height), (0, 0));
+        let rect = Region::Top(50).resolve(0, 0);
+        assert_eq!((rect.width, rect.height), (0, 0));
+    }
 }
@@ -0,0 +1,226 @@
+//! macOS backend, based on CoreGraphics and CoreFoundation
+
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn};
+
+use core_foundation::base::{CFGetTypeID, CFTypeID, ToVoid};
+use core_foundation::string::{
+    kCFStringEncodingUTF8, CFString, CFStringGetCStringPtr, CFStringGetTypeID,
+};
+use core_foundation::number::{
+    CFBooleanGetTypeID, CFNumberGetTypeID, CFNumberGetValue, CFNumberRef,
+    kCFNumberSInt32Type, kCFNumberSInt64Type, kCFNumberFloat32Type, kCFNumberFloat64Type,
+    CFBooleanGetValue, CFNumberGetType,
+};
+use core_foundation::dictionary::{CFDictionaryGetTypeID};
+use core_graphics::display::*;
+use std::ffi::CStr;
+use std::ops::Deref;
+use std::os::raw::c_void;
+
+use crate::{error, Bounds, Frame, FrameSource, Result, Window, WindowProvider};
+
+#[derive(Debug)]
+enum DictEntryValue {
+    _Number(i64),
+    _Float(f64),
+    _Bool(bool),
+    _String(String),
+    _DictRef(CFDictionaryRef),
+    _Unknown,
+}
+
+/// Lists windows with `CGWindowListCopyWindowInfo`, flagging the off-screen ones.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CoreGraphicsProvider;
+
+impl WindowProvider for CoreGraphicsProvider {
+    // From https://github.com/sassman/t-rec-rs/blob/39e7560f06055f15dc4078ea1e65db48b135669a/src/macos/window_id.rs
+    // hard nut to crack, some starting point was:
+    // https://stackoverflow.com/questions/60117318/getting-window-owner-names-via-cgwindowlistcopywindowinfo-in-rust
+    // then some more PRs where needed:
+    // https://github.com/servo/core-foundation-rs/pulls?q=is%3Apr+author%3Asassman+
+    fn windows(&self) -> Result<Vec<Window>> {
+        let mut win_list: Vec<Window> = vec![];
+        let window_list_info = unsafe {
+            CGWindowListCopyWindowInfo(
+                kCGWindowListOptionAll
+                    | kCGWindowListExcludeDesktopElements,
+                kCGNullWindowID,
+            )
+        };
+        if window_list_info.is_null() {
+            return Err(error::Error { kind: error::ErrorKind::CoreFoundation, message: "Cannot get window list results from low level C-API call `CGWindowListCopyWindowInfo` -> null".into() });
+        }
+
+        let count = unsafe { CFArrayGetCount(window_list_info) };
+        for i in 0..count {
+            let dic_ref =
+                unsafe { CFArrayGetValueAtIndex(window_list_info, i as isize) as CFDictionaryRef };
+            if dic_ref.is_null() {
+                unsafe {
+                    CFRelease(window_list_info.cast());
+                }
+                return Err(error::Error { kind: error::ErrorKind::CoreFoundation, message: "Cannot get a result from the window list from low level C-API call `CFArrayGetValueAtIndex` -> null".into() });
+            }
+            let window_name = get_from_dict(dic_ref, "kCGWindowName");
+            let window_owner = get_from_dict(dic_ref, "kCGWindowOwnerName");
+            let window_id = get_from_dict(dic_ref, "kCGWindowNumber");
+            let window_bounds = get_from_dict(dic_ref, "kCGWindowBounds");
+            if let (DictEntryValue::_String(win_name), DictEntryValue::_String(win_owner), DictEntryValue::_Number(win_id)) =
+                (window_name, window_owner, window_id)
+            {
+                let mut w = Window::new(win_id, &win_name, &win_owner, None);
+                // the key is only present for on-screen windows
+                w.set_on_screen(matches!(get_from_dict(dic_ref, "kCGWindowIsOnscreen"), DictEntryValue::_Bool(true)));
+                if let DictEntryValue::_DictRef(b_dic_ref) = window_bounds {
+                    let b_height = get_from_dict(b_dic_ref, "Height");
+                    let b_width = get_from_dict(b_dic_ref, "Width");
+                    let b_x = get_from_dict(b_dic_ref, "X");
+                    let b_y = get_from_dict(b_dic_ref, "Y");
+                    if let (DictEntryValue::_Float(win_height), DictEntryValue::_Float(win_width), DictEntryValue::_Float(win_x), DictEntryValue::_Float(win_y)) =
+                        (b_height, b_width, b_x, b_y)
+                    {
+                        w.bounds = Some(Bounds::new(win_x, win_y, win_width, win_height));
+                        trace!("Window bounds {}, {}, size {} x {}, ", win_x, win_y, win_height, win_width);
+                    }
+                }
+                win_list.push(w);
+            }
+        }
+
+        unsafe {
+            CFRelease(window_list_info.cast());
+        }
+
+        Ok(win_list)
+    }
+}
+
+/// Captures windows with the CoreGraphics API (macOS).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CoreGraphicsSource;
+
+impl FrameSource for CoreGraphicsSource {
+    fn capture(&self, window_id: i64) -> Result<Frame> {
+//TODO: check i32 to u32 cast before
+        let img = unsafe {
+            CGDisplay::screenshot(
+                CGRectNull,
+                kCGWindowListOptionIncludingWindow | kCGWindowListExcludeDesktopElements,
+                window_id as u32,
+                kCGWindowImageBestResolution
+                    | kCGWindowImageBoundsIgnoreFraming
+                    | kCGWindowImageShouldBeOpaque,
+            )
+        };
+        let img = img.ok_or_else(|| error::Error { kind: error::ErrorKind::CaptureFailed, message: format!("Cannot grab screenshot from CGDisplay of window id {}", window_id) })?;
+
+        let cfdata = img.data();
+        let v = cfdata.bytes().to_vec();
+
+        trace!("img {} x {}", img.width(), img.height());
+        trace!("img bits_per_component {}", img.bits_per_component());
+        trace!("img bits_per_pixel {}", img.bits_per_pixel());
+        trace!("img bytes_per_row {}", img.bytes_per_row());
+
+        if img.bytes_per_row() * img.height() != v.len() {
+            return Err(error::Error { kind: error::ErrorKind::CaptureFailed, message: format!("Unexpected stride for the screenshot of window id {} ({} bytes per row, {} bytes)", window_id, img.bytes_per_row(), v.len()) });
+        }
+
+//TODO: check i32 to u32 cast before
+        let bytes_per_pixel = (img.bits_per_pixel() / img.bits_per_component()) as u32;
+        let w = img.bytes_per_row() as u32 / bytes_per_pixel;
+        let h = img.height() as u32;
+        // The bytes per row (also called the “stride”) can be larger than the width of the image.
+        // The extra bytes at the end of each row are simply ignored.
+        // https://stackoverflow.com/a/25706554
+
+        Frame::new(w, h, v)
+    }
+}
+
+fn get_from_dict(dict: CFDictionaryRef, key: &str) -> DictEntryValue {
+    let key: CFString = key.into();
+    let mut value: *const c_void = std::ptr::null();
+    if unsafe { CFDictionaryGetValueIfPresent(dict, key.to_void(), &mut value) != 0 } {
+        let type_id: CFTypeID = unsafe { CFGetTypeID(value) };
+        trace!("key: {:#?} type: {:#?}", key, type_id);
+        if type_id == unsafe { CFNumberGetTypeID() } {
+            let value = value as CFNumberRef;
+            #[allow(non_upper_case_globals)]
+            match unsafe { CFNumberGetType(value) } {
+                kCFNumberSInt64Type => {
+                    trace!("key: {:#?} num type (i64): {:#?}", key, kCFNumberSInt64Type);
+                    let mut value_i64 = 0_i64;
+                    let out_value: *mut i64 = &mut value_i64;
+                    let converted = unsafe { CFNumberGetValue(value, kCFNumberSInt64Type, out_value.cast()) };
+                    if converted {
+                        return DictEntryValue::_Number(value_i64);
+                    }
+                }
+                kCFNumberSInt32Type => {
+                    trace!("key: {:#?} num type (i32): {:#?}", key, kCFNumberSInt32Type);
+                    let mut value_i32 = 0_i32;
+                    let out_value: *mut i32 = &mut value_i32;
+                    let converted = unsafe { CFNumberGetValue(value, kCFNumberSInt32Type, out_value.cast()) };
+                    if converted {
+                        return DictEntryValue::_Number(value_i32 as i64);
+                    }
+                }
+                kCFNumberFloat64Type => {
+                    trace!("key: {:#?} num type (f64): {:#?}", key, kCFNumberFloat64Type);
+                    let mut value_f64 = 0_f64;
+                    let out_value: *mut f64 = &mut value_f64;
+                    let converted = unsafe { CFNumberGetValue(value, kCFNumberFloat64Type, out_value.cast()) };
+                    if converted {
+                        return DictEntryValue::_Float(value_f64);
+                    }
+                }
+                kCFNumberFloat32Type => {
+                    trace!("key: {:#?} num type (f32): {:#?}", key, kCFNumberFloat32Type);
+                    let mut value_f32 = 0_f32;
+                    let out_value: *mut f32 = &mut value_f32;
+                    let converted = unsafe { CFNumberGetValue(value, kCFNumberFloat32Type, out_value.cast()) };
+                    if converted {
+                        return DictEntryValue::_Float(value_f32 as f64);
+                    }
+                }
+                n => {
+                    warn!("Unsupported Number of typeId: {}", n);
+                }
+            }
+        } else if type_id == unsafe { CFBooleanGetTypeID() } {
+            return DictEntryValue::_Bool(unsafe { CFBooleanGetValue(value.cast()) });
+        } else if type_id == unsafe { CFDictionaryGetTypeID() } {
+            return DictEntryValue::_DictRef(value as CFDictionaryRef);
+            //let window_height = get_from_dict(value as CFDictionaryRef, "Height");
+            //trace!("Height={:#?}", window_height);
+        } else if type_id == unsafe { CFStringGetTypeID() } {
+            let c_ptr = unsafe { CFStringGetCStringPtr(value.cast(), kCFStringEncodingUTF8) };
+            return if !c_ptr.is_null() {
+                let c_result = unsafe { CStr::from_ptr(c_ptr) };
+                match c_result.to_str() {
+                    Ok(s) => DictEntryValue::_String(s.to_owned()),
+                    Err(_) => DictEntryValue::_Unknown,
+                }
+            } else {
+                // in this case there is a high chance we got a `NSString` instead of `CFString`
+                // we have to use the objc runtime to fetch it
+                use objc_foundation::{INSString, NSString};
+                use objc_id::Id;
+                let nss: Id<NSString> = unsafe { Id::from_ptr(value as *mut NSString) };
+                let str = std::str::from_utf8(nss.deref().as_str().as_bytes());
+
+                match str {
+                    Ok(s) => DictEntryValue::_String(s.to_owned()),
+                    Err(_) => DictEntryValue::_Unknown,
+                }
+            };
+        } else {
+            warn!("Unexpected type: {}", type_id);
+        }
+    }
+
+    DictEntryValue::_Unknown
+}
@@ -0,0 +1,411 @@
+//! Template matching results
+
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn};
+
+use opencv::{core::Mat, prelude::*, imgproc};
+
+use crate::{error, Rect, Result};
+
+/// Maximum overlap (intersection over union) between two matches, beyond which the worst one is dropped.
+const NMS_MAX_OVERLAP: f64 = 0.3;
+
+/// Enumeration of the template matching methods (see OpenCV `TemplateMatchModes`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchMethod {
+    /// Sum of squared differences, the lower the better.
+    SqDiff,
+    /// Normalized sum of squared differences, the lower the better.
+    SqDiffNormed,
+    /// Cross correlation.
+    CCorr,
+    /// Normalized cross correlation.
+    CCorrNormed,
+    /// Correlation coefficient.
+    CCoeff,
+    /// Normalized correlation coefficient.
+    CCoeffNormed,
+}
+
+impl MatchMethod {
+    /// Returns the OpenCV constant of the method.
+    pub fn to_cv(self) -> i32 {
+        match self {
+            MatchMethod::SqDiff => imgproc::TM_SQDIFF,
+            MatchMethod::SqDiffNormed => imgproc::TM_SQDIFF_NORMED,
+            MatchMethod::CCorr => imgproc::TM_CCORR,
+            MatchMethod::CCorrNormed => imgproc::TM_CCORR_NORMED,
+            MatchMethod::CCoeff => imgproc::TM_CCOEFF,
+            MatchMethod::CCoeffNormed => imgproc::TM_CCOEFF_NORMED,
+        }
+    }
+
+    /// Returns `true` if the best match has the lowest score (SQDIFF methods).
+    pub fn lower_is_better(self) -> bool {
+        matches!(self, MatchMethod::SqDiff | MatchMethod::SqDiffNormed)
+    }
+
+    /// Returns a threshold suited to the method.
+    ///
+    /// Scores of the unnormalized methods depend on the size of the template, their default threshold
+    /// rejects every location and must be replaced by a calibrated one.
+    pub fn default_threshold(self) -> f64 {
+        match self {
+            MatchMethod::SqDiffNormed => 0.1,
+            MatchMethod::CCorrNormed => 0.97,
+            MatchMethod::CCoeffNormed => 0.8,
+            MatchMethod::SqDiff => 0.0,
+            MatchMethod::CCorr | MatchMethod::CCoeff => f64::MAX,
+        }
+    }
+
+    /// Returns `true` if the method accepts a mask (TM_SQDIFF and TM_CCORR_NORMED).
+    pub fn supports_mask(self) -> bool {
+        matches!(self, MatchMethod::SqDiff | MatchMethod::CCorrNormed)
+    }
+}
+
+/// Structure representing a range of template scales to search, for DPI and zoom independence.
+///
+/// The range is first searched every `step`, then every quarter of `step` around the best coarse scale.
+/// A template captured on a Retina screen is found on a 1x screen at the scale 0.5.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScaleRange {
+    /// Smallest scale.
+    pub min: f64,
+    /// Largest scale.
+    pub max: f64,
+    /// Gap between two scales of the coarse pass.
+    pub step: f64,
+}
+
+impl ScaleRange {
+    /// Creates a new `ScaleRange`.
+    pub fn new(min: f64, max: f64, step: f64) -> Self {
+        ScaleRange { min, max, step }
+    }
+
+    /// Returns the scales of the coarse pass, from `min` to `max`.
+    pub fn coarse(&self) -> Vec<f64> {
+        if self.step <= 0.0 || self.max <= self.min {
+            return vec![self.min];
+        }
+        let count = ((self.max - self.min) / self.step + 1e-9).floor() as usize;
+        (0..=count).map(|i| self.min + i as f64 * self.step).collect()
+    }
+
+    /// Returns the scales of the fine pass around the given coarse scale, excluding it.
+    pub fn fine(&self, around: f64) -> Vec<f64> {
+        if self.step <= 0.0 {
+            return vec![];
+        }
+        let fine_step = self.step / 4.0;
+        (-3..=3)
+            .filter(|i| *i != 0)
+            .map(|i| around + i as f64 * fine_step)
+            .filter(|scale| *scale >= self.min && *scale <= self.max)
+            .collect()
+    }
+}
+
+/// Structure representing the settings of a template search.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchOptions {
+    /// Matching method, `None` to pick it from the template: `CCorrNormed` if it has a mask, `CCoeffNormed` otherwise.
+    pub method: Option<MatchMethod>,
+    /// Score a location must exceed (or stay under, for SQDIFF methods) to be a match,
+    /// `None` for the default threshold of the method.
+    ///
+    /// The scale depends on the method: keep it in line when setting the method.
+    pub threshold: Option<f64>,
+    /// Matches in grayscale (faster) rather than in color.
+    pub grayscale: bool,
+    /// Scales of the template to search, only the native scale if `None`.
+    pub scales: Option<ScaleRange>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            method: None,
+            threshold: None,
+            grayscale: true,
+            scales: None,
+        }
+    }
+}
+
+impl MatchOptions {
+    /// Creates a new `MatchOptions` for the given method, with its default threshold.
+    pub fn new(method: MatchMethod) -> Self {
+        MatchOptions { method: Some(method), ..MatchOptions::default() }
+    }
+
+    /// Returns the matching method, `CCoeffNormed` if automatic.
+    pub fn method(&self) -> MatchMethod {
+        self.method.unwrap_or(MatchMethod::CCoeffNormed)
+    }
+
+    /// Returns the threshold, the default one of the method if not set.
+    pub fn threshold(&self) -> f64 {
+        self.threshold.unwrap_or_else(|| self.method().default_threshold())
+    }
+
+    /// Returns `true` if the score is good enough to be a match.
+    pub fn accepts(&self, score: f64) -> bool {
+        if self.method().lower_is_better() {
+            score < self.threshold()
+        } else {
+            score > self.threshold()
+        }
+    }
+
+    /// Returns the options to use with a masked template.
+    ///
+    /// An automatic method becomes `CCorrNormed`, along with its default threshold unless one is set.
+    /// Fails if the method set does not accept a mask: pick `SqDiff` or `CCorrNormed` with a threshold calibrated for it.
+    pub fn masked(&self) -> Result<MatchOptions> {
+        match self.method {
+            None => Ok(MatchOptions { method: Some(MatchMethod::CCorrNormed), ..self.clone() }),
+            Some(method) if !method.supports_mask() => {
+                Err(error::Error { kind: error::ErrorKind::InvalidOptions, message: format!("{:?} does not accept a mask, use SqDiff or CCorrNormed", method) })
+            }
+            Some(_) => Ok(self.clone()),
+        }
+    }
+
+    /// Returns `true` if the first score is better than the second one.
+    pub fn is_better(&self, score: f64, other: f64) -> bool {
+        if self.method().lower_is_better() {
+            score < other
+        } else {
+            score > other
+        }
+    }
+}
+
+/// Structure representing an occurrence of a template in the window.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Match {
+    /// Location of the template, relative to the window.
+    pub rect: Rect,
+    /// Matching score (the lower, the better for SQDIFF methods, the higher otherwise).
+    pub score: f64,
+    /// Identifier of the template (usually its path).
+    pub template_id: String,
+    /// Index of the capture the template was found in, starting from 0.
+    pub frame_index: u32,
+    /// Time elapsed from the beginning of the search.
+    pub elapsed: Duration,
+    /// Scale of the template the match was found at, 1.0 being its native size.
+    pub scale: f64,
+}
+
+impl Match {
+    /// Creates a new `Match`, found in the first frame.
+    pub fn new(rect: Rect, score: f64, template_id: &str) -> Self {
+        Match {
+            rect,
+            score,
+            template_id: template_id.to_string(),
+            frame_index: 0,
+            elapsed: Duration::ZERO,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Returns the local maxima of the `match_template` result above the threshold, without template id.
+///
+/// A location is kept if none of its 8 neighbours has a better score, so that the few pixels around
+/// each occurrence do not all become candidates.
+pub(crate) fn peaks(result: &Mat, tpl_width: u32, tpl_height: u32, options: &MatchOptions) -> Result<Vec<Match>> {
+    let cols = result.cols() as usize;
+    let scores = result.data_typed::<f32>()?;
+    Ok(local_maxima(scores, cols, options)
+        .into_iter()
+        .map(|i| Match::new(Rect::new((i % cols) as u32, (i / cols) as u32, tpl_width, tpl_height), scores[i] as f64, ""))
+        .collect())
+}
+
+// Indexes of the accepted scores which are the best of their 3 x 3 neighbourhood,
+// the first location in reading order winning among equal scores
+fn local_maxima(scores: &[f32], cols: usize, options: &MatchOptions) -> Vec<usize> {
+    if cols == 0 {
+        return vec![];
+    }
+    let rows = scores.len() / cols;
+    let mut maxima = vec![];
+    for (i, score) in scores.iter().enumerate() {
+        let score = *score as f64;
+        if !options.accepts(score) {
+            continue;
+        }
+        let (x, y) = (i % cols, i / cols);
+        let is_peak = (y.saturating_sub(1)..(y + 2).min(rows))
+            .flat_map(|ny| (x.saturating_sub(1)..(x + 2).min(cols)).map(move |nx| ny * cols + nx))
+            .filter(|j| *j != i)
+            .all(|j| {
+                let other = scores[j] as f64;
+                !(options.is_better(other, score) || (other == score && j < i))
+            });
+        if is_peak {
+            maxima.push(i);
+        }
+    }
+    maxima
+}
+
+/// Sorts the candidates best score first and collapses the overlapping ones (non-maximum suppression).
+pub(crate) fn suppress(mut candidates: Vec<Match>, options: &MatchOptions) -> Vec<Match> {
+    if options.method().lower_is_better() {
+        candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+    } else {
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    }
+
+    let mut matches: Vec<Match> = vec![];
+    for candidate in candidates {
+        if matches.iter().all(|m| overlap(&m.rect, &candidate.rect) <= NMS_MAX_OVERLAP) {
+            matches.push(candidate);
+        }
+    }
+    matches
+}
+
+/// Sorts matches in reading order: top to bottom, then left to right.
+///
+/// Matches whose tops are less than half a height apart belong to the same row.
+pub fn sort_by_reading_order(matches: &mut [Match]) {
+    matches.sort_by_key(|m| m.rect.y);
+    let mut start = 0;
+    while start < matches.len() {
+        let (row_y, tolerance) = (matches[start].rect.y, matches[start].rect.height / 2);
+        let mut end = start + 1;
+        while end < matches.len() && matches[end].rect.y - row_y <= tolerance {
+            end += 1;
+        }
+        matches[start..end].sort_by_key(|m| m.rect.x);
+        start = end;
+    }
+}
+
+// Intersection over union of two rectangles
+fn overlap(a: &Rect, b: &Rect) -> f64 {
+    let (left, top) = (a.x.max(b.x), a.y.max(b.y));
+    let (right, bottom) = ((a.x + a.width).min(b.x + b.width), (a.y + a.height).min(b.y + b.height));
+    if right <= left || bottom <= top {
+        return 0.0;
+    }
+    let intersection = (right - left) as f64 * (bottom - top) as f64;
+    let union = a.width as f64 * a.height as f64 + b.width as f64 * b.height as f64 - intersection;
+    intersection / union
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(x: u32, y: u32, score: f64) -> Match {
+        Match::new(Rect::new(x, y, 10, 10), score, "")
+    }
+
+    #[test]
+    fn overlap_is_intersection_over_union() {
+        let a = Rect::new(0, 0, 10, 10);
+        assert_eq!(overlap(&a, &a), 1.0);
+        assert_eq!(overlap(&a, &Rect::new(10, 0, 10, 10)), 0.0);
+        assert_eq!(overlap(&a, &Rect::new(5, 0, 10, 10)), 50.0 / 150.0);
+        assert_eq!(overlap(&a, &Rect::new(2, 2, 5, 5)), 0.25);
+    }
+
+    #[test]
+    fn suppress_keeps_best_of_overlapping() {
+        let candidates = vec![candidate(0, 0, 0.85), candidate(1, 1, 0.95), candidate(50, 0, 0.9), candidate(8, 0, 0.99)];
+        let matches = suppress(candidates, &MatchOptions::default());
+        let kept: Vec<(u32, u32)> = matches.iter().map(|m| (m.rect.x, m.rect.y)).collect();
+        assert_eq!(kept, vec![(8, 0), (1, 1), (50, 0)]);
+    }
+
+    #[test]
+    fn suppress_lower_is_better() {
+        let options = MatchOptions::new(MatchMethod::SqDiffNormed);
+        let matches = suppress(vec![candidate(0, 0, 0.05), candidate(1, 0, 0.01)], &options);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].score, 0.01);
+    }
+
+    #[test]
+    fn reading_order_groups_rows() {
+        let mut matches = vec![candidate(40, 22, 0.9), candidate(30, 3, 0.9), candidate(0, 25, 0.9), candidate(10, 0, 0.9)];
+        sort_by_reading_order(&mut matches);
+        let order: Vec<(u32, u32)> = matches.iter().map(|m| (m.rect.x, m.rect.y)).collect();
+        assert_eq!(order, vec![(10, 0), (30, 3), (0, 25), (40, 22)]);
+    }
+
+    #[test]
+    fn new_options_have_a_threshold_suited_to_the_method() {
+        assert_eq!(MatchOptions::default().threshold(), MatchOptions::new(MatchMethod::CCoeffNormed).threshold());
+        let options = MatchOptions::new(MatchMethod::SqDiffNormed);
+        assert!(options.accepts(0.01));
+        assert!(!options.accepts(0.5));
+        assert!(!MatchOptions::new(MatchMethod::SqDiff).accepts(1000.0));
+        assert!(!MatchOptions::new(MatchMethod::CCoeff).accepts(1e12));
+    }
+
+    #[test]
+    fn coarse_scales_cover_the_range() {
+        assert_eq!(ScaleRange::new(0.5, 1.0, 0.25).coarse(), vec![0.5, 0.75, 1.0]);
+        // the last step may not reach the maximum
+        assert_eq!(ScaleRange::new(0.5, 1.0, 0.3).coarse(), vec![0.5, 0.8]);
+        // rounding errors must not drop the maximum
+        assert_eq!(ScaleRange::new(0.7, 1.0, 0.1).coarse().len(), 4);
+        assert_eq!(ScaleRange::new(1.0, 1.0, 0.1).coarse(), vec![1.0]);
+        assert_eq!(ScaleRange::new(0.5, 1.0, 0.0).coarse(), vec![0.5]);
+    }
+
+    #[test]
+    fn fine_scales_stay_in_the_range() {
+        let range = ScaleRange::new(0.5, 1.0, 0.2);
+        let fine = range.fine(0.7);
+        let expected = [0.55, 0.6, 0.65, 0.75, 0.8, 0.85];
+        assert_eq!(fine.len(), expected.len());
+        assert!(fine.iter().zip(expected.iter()).all(|(s, e)| (s - e).abs() < 1e-9));
+        // scales below the minimum are dropped
+        let fine = range.fine(0.5);
+        assert_eq!(fine.len(), 3);
+        assert!(fine.iter().all(|s| *s > 0.5 && *s < 0.66));
+        assert!(ScaleRange::new(0.5, 1.0, 0.0).fine(0.5).is_empty());
+    }
+
+    #[test]
+    fn masked_options() {
+        // an automatic method switches to CCorrNormed and its threshold
+        let masked = MatchOptions::default().masked().unwrap();
+        assert_eq!(masked.method(), MatchMethod::CCorrNormed);
+        assert_eq!(masked.threshold(), MatchMethod::CCorrNormed.default_threshold());
+        // a method set by the caller is kept, or rejected
+        assert!(MatchOptions::new(MatchMethod::CCoeffNormed).masked().is_err());
+        let options = MatchOptions { threshold: Some(0.98), ..MatchOptions::new(MatchMethod::CCorrNormed) };
+        assert_eq!(options.masked().unwrap(), options);
+    }
+
+    #[test]
+    fn local_maxima_of_a_blob() {
+        let scores = [
+            0.1, 0.2, 0.1, 0.0, 0.0,
+            0.2, 0.9, 0.85, 0.0, 0.95,
+            0.1, 0.85, 0.9, 0.0, 0.0,
+        ];
+        assert_eq!(local_maxima(&scores, 5, &MatchOptions::default()), vec![6, 9]);
+        // equal scores give a single peak
+        assert_eq!(local_maxima(&[0.9, 0.9, 0.9, 0.9], 2, &MatchOptions::default()), vec![0]);
+        assert!(local_maxima(&scores, 5, &MatchOptions { threshold: Some(0.99), ..MatchOptions::default() }).is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! Mouse pointer motion profiles
+
+use std::time::Duration;
+
+/// Enumeration of the ways the mouse pointer moves from one point to another.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MotionProfile {
+    /// Jumps straight to the target.
+    #[default]
+    Instant,
+    /// Moves along a straight line at constant speed.
+    Linear {
+        /// Number of moves.
+        steps: u32,
+        /// Duration of the whole motion.
+        duration: Duration,
+    },
+    /// Moves along a random curve, slowly at both ends, with some jitter on the way.
+    Bezier {
+        /// Number of moves.
+        steps: u32,
+        /// Duration of the whole motion.
+        duration: Duration,
+        /// Maximum random offset of the intermediate positions, in screen points.
+        jitter: f64,
+        /// Seed of the random generator, the same seed gives the same paths.
+        seed: u64,
+    },
+}
+
+impl MotionProfile {
+    /// Returns a human-like profile, with the given seed.
+    pub fn human(seed: u64) -> Self {
+        MotionProfile::Bezier {
+            steps: 30,
+            duration: Duration::from_millis(350),
+            jitter: 1.5,
+            seed,
+        }
+    }
+
+    /// Returns the duration of a motion.
+    pub fn duration(&self) -> Duration {
+        match self {
+            MotionProfile::Instant => Duration::ZERO,
+            MotionProfile::Linear { duration, .. } | MotionProfile::Bezier { duration, .. } => *duration,
+        }
+    }
+
+    /// Returns the same profile with the given timing, instant motions becoming linear.
+    pub fn with_timing(&self, steps: u32, duration: Duration) -> Self {
+        match *self {
+            MotionProfile::Instant | MotionProfile::Linear { .. } => MotionProfile::Linear { steps, duration },
+            MotionProfile::Bezier { jitter, seed, .. } => MotionProfile::Bezier { steps, duration, jitter, seed },
+        }
+    }
+}
+
+/// Returns the successive positions of the pointer moving from `from` to `to`, the last one being `to`.
+///
+/// `index` is the number of the motion, so that successive motions of a seeded profile differ
+/// while the whole sequence stays reproducible.
+///
+/// ```
+/// use std::time::Duration;
+/// use macmatic::motion::{path, MotionProfile};
+///
+/// let profile = MotionProfile::Bezier { steps: 10, duration: Duration::ZERO, jitter: 2.0, seed: 42 };
+/// let points = path(&profile, (0, 0), (300, 200), 0);
+/// assert_eq!(points.len(), 10);
+/// assert_eq!(points.last(), Some(&(300, 200)));
+/// assert_eq!(points, path(&profile, (0, 0), (300, 200), 0));
+/// assert_eq!(path(&MotionProfile::Instant, (0, 0), (300, 200), 0), vec![(300, 200)]);
+/// ```
+pub fn path(profile: &MotionProfile, from: (i32, i32), to: (i32, i32), index: u64) -> Vec<(i32, i32)> {
+    if from == to {
+        return vec![to];
+    }
+    let (x0, y0) = (from.0 as f64, from.1 as f64);
+    let (x3, y3) = (to.0 as f64, to.1 as f64);
+    match *profile {
+        MotionProfile::Instant => vec![to],
+        MotionProfile::Linear { steps, .. } => {
+            let steps = steps.max(1);
+            (1..=steps)
+                .map(|i| {
+                    let t = i as f64 / steps as f64;
+                    ((x0 + (x3 - x0) * t).round() as i32, (y0 + (y3 - y0) * t).round() as i32)
+                })
+                .collect()
+        }
+        MotionProfile::Bezier { steps, jitter, seed, .. } => {
+            let steps = steps.max(1);
+            let mut rng = SplitMix64::new(seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            // control points are pushed away from the straight line by up to 30% of its length
+            let (dx, dy) = (x3 - x0, y3 - y0);
+            let (nx, ny) = (-dy, dx);
+            let (k1, k2) = (rng.next_signed() * 0.3, rng.next_signed() * 0.3);
+            let (x1, y1) = (x0 + dx / 3.0 + nx * k1, y0 + dy / 3.0 + ny * k1);
+            let (x2, y2) = (x0 + dx * 2.0 / 3.0 + nx * k2, y0 + dy * 2.0 / 3.0 + ny * k2);
+            (1..=steps)
+                .map(|i| {
+                    if i == steps {
+                        return to;
+                    }
+                    // ease in and out
+                    let s = i as f64 / steps as f64;
+                    let t = s * s * (3.0 - 2.0 * s);
+                    let u = 1.0 - t;
+                    let x = u * u * u * x0 + 3.0 * u * u * t * x1 + 3.0 * u * t * t * x2 + t * t * t * x3;
+                    let y = u * u * u * y0 + 3.0 * u * u * t * y1 + 3.0 * u * t * t * y2 + t * t * t * y3;
+                    ((x + rng.next_signed() * jitter).round() as i32, (y + rng.next_signed() * jitter).round() as i32)
+                })
+                .collect()
+        }
+    }
+}
+
+// Small and fast pseudo-random generator, good enough for pointer motions
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform value in [-1, 1)
+    fn next_signed(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_steps_are_evenly_spaced() {
+        let profile = MotionProfile::Linear { steps: 4, duration: Duration::ZERO };
+        assert_eq!(path(&profile, (0, 0), (100, 50), 0), vec![(25, 13), (50, 25), (75, 38), (100, 50)]);
+        assert_eq!(path(&profile, (100, 50), (0, 0), 0), vec![(75, 38), (50, 25), (25, 13), (0, 0)]);
+    }
+
+    #[test]
+    fn bezier_paths_depend_on_seed_and_index() {
+        let profile = MotionProfile::Bezier { steps: 20, duration: Duration::ZERO, jitter: 1.0, seed: 1 };
+        let other_seed = MotionProfile::Bezier { steps: 20, duration: Duration::ZERO, jitter: 1.0, seed: 2 };
+        let first = path(&profile, (0, 0), (400, 300), 0);
+        assert_eq!(first, path(&profile, (0, 0), (400, 300), 0));
+        assert_ne!(first, path(&profile, (0, 0), (400, 300), 1));
+        assert_ne!(first, path(&other_seed, (0, 0), (400, 300), 0));
+    }
+
+    #[test]
+    fn bezier_jitter_stays_in_bounds() {
+        let (from, to) = ((0, 0), (400, 300));
+        for seed in 0..20 {
+            let smooth = MotionProfile::Bezier { steps: 20, duration: Duration::ZERO, jitter: 0.0, seed };
+            let jittered = MotionProfile::Bezier { steps: 20, duration: Duration::ZERO, jitter: 3.0, seed };
+            let smooth = path(&smooth, from, to, 0);
+            let jittered = path(&jittered, from, to, 0);
+            assert_eq!(jittered.len(), 20);
+            assert_eq!(jittered.last(), Some(&to));
+            // the jitter moves each position by at most 3 points, plus one for the rounding
+            for (a, b) in smooth.iter().zip(jittered.iter()) {
+                assert!((a.0 - b.0).abs() <= 4 && (a.1 - b.1).abs() <= 4, "{:?} too far from {:?}", b, a);
+            }
+            // the control points stay within 30% of the length from the straight line, which bounds the curve
+            for (x, y) in smooth {
+                let distance = (3.0 * x as f64 - 4.0 * y as f64).abs() / 5.0;
+                assert!(distance <= 0.3 * 500.0 + 1.0, "({}, {}) too far from the line", x, y);
+            }
+        }
+    }
+}
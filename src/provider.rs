@@ -0,0 +1,46 @@
+//! Window enumeration providers
+
+use std::fmt;
+
+use crate::{Result, Window};
+
+/// A provider listing the windows a `Bot` can interact with.
+pub trait WindowProvider: fmt::Debug {
+    /// Returns the windows currently available, front to back, including the off-screen ones.
+    fn windows(&self) -> Result<Vec<Window>>;
+}
+
+/// A provider returning a fixed list of windows, useful for testing.
+#[derive(Clone, Debug, Default)]
+pub struct StaticWindowProvider(Vec<Window>);
+
+impl StaticWindowProvider {
+    /// Creates a new `StaticWindowProvider` returning the given windows.
+    pub fn new(windows: Vec<Window>) -> Self {
+        StaticWindowProvider(windows)
+    }
+}
+
+impl WindowProvider for StaticWindowProvider {
+    fn windows(&self) -> Result<Vec<Window>> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Returns the window provider of the current platform.
+#[cfg(target_os = "macos")]
+pub(crate) fn default_provider() -> Box<dyn WindowProvider> {
+    Box::new(crate::macos::CoreGraphicsProvider)
+}
+
+/// Returns the window provider of the current platform.
+#[cfg(all(target_os = "linux", feature = "x11"))]
+pub(crate) fn default_provider() -> Box<dyn WindowProvider> {
+    Box::new(crate::x11::X11Provider)
+}
+
+/// Returns the window provider of the current platform.
+#[cfg(not(any(target_os = "macos", all(target_os = "linux", feature = "x11"))))]
+pub(crate) fn default_provider() -> Box<dyn WindowProvider> {
+    Box::new(StaticWindowProvider::default())
+}
@@ -0,0 +1,190 @@
+//! Window selection
+
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn};
+use regex::Regex;
+
+use crate::{error, Result, Window};
+
+/// Builder of the criteria a window must meet to be selected by a `Bot`.
+///
+/// Window providers list windows front to back, off-screen ones included. Unless `nth` or `frontmost` is set,
+/// the selection fails when several windows meet the criteria.
+///
+/// ```no_run
+/// use macmatic::{Bot, WindowQuery};
+///
+/// let mut bot = Bot::new();
+/// bot.set_window(&WindowQuery::new().owner("Preview").title_regex(r"\.png$").min_size(200.0, 200.0)).unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WindowQuery {
+    id: Option<i64>,
+    title: Option<String>,
+    title_regex: Option<String>,
+    owner: Option<String>,
+    owner_regex: Option<String>,
+    min_size: Option<(f64, f64)>,
+    on_screen_only: bool,
+    nth: Option<usize>,
+    frontmost: bool,
+}
+
+impl WindowQuery {
+    /// Creates a new `WindowQuery` accepting any window.
+    pub fn new() -> Self {
+        WindowQuery::default()
+    }
+
+    /// Only accepts the window with the given id.
+    pub fn id(mut self, id: i64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Only accepts windows with the given name.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    /// Only accepts windows whose name matches the given regex.
+    pub fn title_regex(mut self, regex: &str) -> Self {
+        self.title_regex = Some(regex.to_string());
+        self
+    }
+
+    /// Only accepts windows of the given owner (application).
+    pub fn owner(mut self, owner: &str) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    /// Only accepts windows whose owner name matches the given regex.
+    pub fn owner_regex(mut self, regex: &str) -> Self {
+        self.owner_regex = Some(regex.to_string());
+        self
+    }
+
+    /// Only accepts windows at least as large as the given size, in screen points.
+    pub fn min_size(mut self, width: f64, height: f64) -> Self {
+        self.min_size = Some((width, height));
+        self
+    }
+
+    /// Only accepts windows reported on screen by the provider, rejecting minimized or hidden ones.
+    pub fn on_screen_only(mut self) -> Self {
+        self.on_screen_only = true;
+        self
+    }
+
+    /// Selects the nth matching window, front to back, starting from 0.
+    pub fn nth(mut self, n: usize) -> Self {
+        self.nth = Some(n);
+        self
+    }
+
+    /// Selects the frontmost matching window instead of failing when several windows match.
+    pub fn frontmost(mut self) -> Self {
+        self.frontmost = true;
+        self
+    }
+
+    /// Returns the windows meeting the criteria, front to back.
+    pub fn filter<'a>(&self, windows: &'a [Window]) -> Result<Vec<&'a Window>> {
+        let title_regex = self.title_regex.as_deref().map(Regex::new).transpose()?;
+        let owner_regex = self.owner_regex.as_deref().map(Regex::new).transpose()?;
+        Ok(windows.iter().filter(|w| self._accepts(w, title_regex.as_ref(), owner_regex.as_ref())).collect())
+    }
+
+    /// Returns the single window meeting the criteria.
+    pub fn select<'a>(&self, windows: &'a [Window]) -> Result<&'a Window> {
+        let candidates = self.filter(windows)?;
+        debug!("{} window(s) matching {:?}", candidates.len(), self);
+        let selected = match self.nth {
+            Some(n) => candidates.get(n),
+            None if candidates.len() > 1 && !self.frontmost => {
+                let ids: Vec<String> = candidates.iter().map(|w| w.id.to_string()).collect();
+                return Err(error::Error { kind: error::ErrorKind::AmbiguousWindow, message: format!("windows {} all match {:?}", ids.join(", "), self) });
+            }
+            None => candidates.first(),
+        };
+        selected.copied().ok_or_else(|| error::Error { kind: error::ErrorKind::NoWindow, message: format!("no window matching {:?}", self) })
+    }
+
+    fn _accepts(&self, w: &Window, title_regex: Option<&Regex>, owner_regex: Option<&Regex>) -> bool {
+        if self.id.is_some() && self.id != Some(w.id) {
+            return false;
+        }
+        if self.title.as_ref().is_some_and(|title| w.name.ne(title)) {
+            return false;
+        }
+        if title_regex.is_some_and(|re| !re.is_match(&w.name)) {
+            return false;
+        }
+        if self.owner.as_ref().is_some_and(|owner| w.owner_name.ne(owner)) {
+            return false;
+        }
+        if owner_regex.is_some_and(|re| !re.is_match(&w.owner_name)) {
+            return false;
+        }
+        if self.on_screen_only && !w.on_screen {
+            return false;
+        }
+        match (self.min_size, w.bounds.as_ref()) {
+            (None, _) => true,
+            (Some((width, height)), Some(bounds)) => bounds.width >= width && bounds.height >= height,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::ErrorKind, Bounds};
+
+    fn windows() -> Vec<Window> {
+        let mut hidden = Window::new(3, "Untitled", "TextEdit", Some(Bounds::new(0.0, 0.0, 800.0, 600.0)));
+        hidden.set_on_screen(false);
+        vec![
+            Window::new(1, "Untitled", "TextEdit", Some(Bounds::new(0.0, 0.0, 800.0, 600.0))),
+            Window::new(2, "Untitled", "TextEdit", Some(Bounds::new(0.0, 0.0, 100.0, 50.0))),
+            hidden,
+            Window::new(4, "notes.txt", "TextEdit", None),
+        ]
+    }
+
+    #[test]
+    fn select_fails_when_ambiguous() {
+        let windows = windows();
+        let err = WindowQuery::new().title("Untitled").select(&windows).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::AmbiguousWindow));
+        assert_eq!(WindowQuery::new().title("Untitled").frontmost().select(&windows).unwrap().id, 1);
+        let err = WindowQuery::new().owner("Preview").select(&windows).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NoWindow));
+    }
+
+    #[test]
+    fn select_nth() {
+        let windows = windows();
+        assert_eq!(WindowQuery::new().title("Untitled").nth(1).select(&windows).unwrap().id, 2);
+        assert_eq!(WindowQuery::new().title("Untitled").nth(2).select(&windows).unwrap().id, 3);
+        assert!(WindowQuery::new().title("Untitled").nth(3).select(&windows).is_err());
+    }
+
+    #[test]
+    fn select_min_size_and_on_screen() {
+        let windows = windows();
+        assert_eq!(WindowQuery::new().title_regex("^Unt").min_size(200.0, 200.0).on_screen_only().select(&windows).unwrap().id, 1);
+        // windows without bounds never meet a minimum size
+        assert!(WindowQuery::new().title("notes.txt").min_size(1.0, 1.0).select(&windows).is_err());
+        assert_eq!(WindowQuery::new().title("notes.txt").select(&windows).unwrap().id, 4);
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let err = WindowQuery::new().owner_regex("(").select(&windows()).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidRegex(_)));
+    }
+}
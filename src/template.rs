@@ -0,0 +1,313 @@
+//! Template images
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn};
+
+use opencv::{
+    core::{self, Mat},
+    prelude::*,
+    imgcodecs,
+    imgproc,
+};
+
+use crate::{error, Result};
+
+/// Minimum alpha (or mask) value of a pixel to take part in the matching.
+const MASK_THRESHOLD: f64 = 127.0;
+
+/// Structure representing an image to search for, decoded once.
+///
+/// Transparent pixels of a PNG with an alpha channel are ignored during the matching,
+/// which requires a method accepting a mask (see `MatchOptions::masked`).
+/// Cloning a `Template` is cheap, the pixels are shared.
+#[derive(Clone, Debug)]
+pub struct Template(Rc<TemplateData>);
+
+#[derive(Debug)]
+struct TemplateData {
+    id: String,
+    gray: Mat,
+    color: Mat,
+    mask: Option<Mat>,
+}
+
+impl Template {
+    /// Loads a `Template` from an image file.
+    pub fn from_file(file: &Path) -> Result<Self> {
+        let img = imgcodecs::imread(&file.to_string_lossy(), imgcodecs::IMREAD_UNCHANGED)?;
+        Template::_from_mat(&file.display().to_string(), img, None)
+    }
+
+    /// Loads a `Template` from an image file, with an explicit mask image of the same size.
+    ///
+    /// Black pixels of the mask are ignored during the matching, whatever the alpha channel of the template.
+    pub fn from_file_with_mask(file: &Path, mask_file: &Path) -> Result<Self> {
+        let img = imgcodecs::imread(&file.to_string_lossy(), imgcodecs::IMREAD_UNCHANGED)?;
+        let mask = imgcodecs::imread(&mask_file.to_string_lossy(), imgcodecs::IMREAD_GRAYSCALE)?;
+        if mask.rows() != img.rows() || mask.cols() != img.cols() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Mask {} does not have the size of template {}", mask_file.display(), file.display())).into());
+        }
+        Template::_from_mat(&file.display().to_string(), img, Some(mask))
+    }
+
+    /// Loads a `Template` from an encoded image (PNG, JPEG...) held in memory.
+    pub fn from_bytes(id: &str, bytes: &[u8]) -> Result<Self> {
+        let img = imgcodecs::imdecode(&core::Vector::<u8>::from_slice(bytes), imgcodecs::IMREAD_UNCHANGED)?;
+        Template::_from_mat(id, img, None)
+    }
+
+    /// Returns the identifier of the template (its path when loaded from a file).
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    /// Returns the width of the template, in pixels.
+    pub fn width(&self) -> u32 {
+        self.0.gray.cols() as u32
+    }
+
+    /// Returns the height of the template, in pixels.
+    pub fn height(&self) -> u32 {
+        self.0.gray.rows() as u32
+    }
+
+    /// Returns the template image, either grayscale or BGR.
+    pub fn image(&self, grayscale: bool) -> &Mat {
+        if grayscale { &self.0.gray } else { &self.0.color }
+    }
+
+    /// Returns the binary mask of the template, if some of its pixels are transparent.
+    pub fn mask(&self) -> Option<&Mat> {
+        self.0.mask.as_ref()
+    }
+
+    /// Returns the size of the template resized by the given factor, rounded to the nearest pixel.
+    pub fn scaled_size(&self, scale: f64) -> (u32, u32) {
+        let size = |n: u32| (n as f64 * scale).round().max(0.0) as u32;
+        (size(self.width()), size(self.height()))
+    }
+
+    /// Returns a copy of the template resized by the given factor.
+    ///
+    /// Fails with `ErrorKind::InvalidOptions` if the factor shrinks the template to nothing.
+    pub fn scaled(&self, scale: f64) -> Result<Self> {
+        if scale == 1.0 {
+            return Ok(self.clone());
+        }
+        let (width, height) = self.scaled_size(scale);
+        if width == 0 || height == 0 {
+            return Err(error::Error {
+                kind: error::ErrorKind::InvalidOptions,
+                message: format!("Template {} is empty at scale {}", self.0.id, scale),
+            });
+        }
+        let size = core::Size::new(width as i32, height as i32);
+        // shrink with pixel area relation to avoid moire
+        let interpolation = if scale < 1.0 { imgproc::INTER_AREA } else { imgproc::INTER_LINEAR };
+        let mask = match self.0.mask.as_ref() {
+            Some(mask) => Some(Template::_resize(mask, size, imgproc::INTER_NEAREST)?),
+            None => None,
+        };
+        Ok(Template(Rc::new(TemplateData {
+            id: self.0.id.clone(),
+            gray: Template::_resize(&self.0.gray, size, interpolation)?,
+            color: Template::_resize(&self.0.color, size, interpolation)?,
+            mask,
+        })))
+    }
+
+    fn _from_mat(id: &str, img: Mat, mask: Option<Mat>) -> Result<Self> {
+        if img.rows() == 0 || img.cols() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Cannot decode template {}", id)).into());
+        }
+        let (to_gray, to_color) = match img.channels() {
+            1 => (None, Some(imgproc::COLOR_GRAY2BGR)),
+            4 => (Some(imgproc::COLOR_BGRA2GRAY), Some(imgproc::COLOR_BGRA2BGR)),
+            _ => (Some(imgproc::COLOR_BGR2GRAY), None),
+        };
+        let mask = match mask {
+            Some(mask) => Some(Template::_binarize(&mask)?),
+            None if img.channels() == 4 => Template::_alpha_mask(&img)?,
+            None => None,
+        };
+        let gray = match to_gray {
+            Some(code) => Template::_convert(&img, code)?,
+            None => img.try_clone()?,
+        };
+        let color = match to_color {
+            Some(code) => Template::_convert(&img, code)?,
+            None => img,
+        };
+        trace!("template {} = {} x {} (mask: {})", id, gray.cols(), gray.rows(), mask.is_some());
+        Ok(Template(Rc::new(TemplateData { id: id.to_string(), gray, color, mask })))
+    }
+
+    // Returns the mask made of the alpha channel, or `None` if the image is fully opaque
+    fn _alpha_mask(img: &Mat) -> Result<Option<Mat>> {
+        let mut alpha = Mat::default();
+        core::extract_channel(img, &mut alpha, 3)?;
+        let mut min_alpha: f64 = 0.0;
+        core::min_max_loc(&alpha, Some(&mut min_alpha), None, None, None, &Mat::default())?;
+        if min_alpha > MASK_THRESHOLD {
+            return Ok(None);
+        }
+        Ok(Some(Template::_binarize(&alpha)?))
+    }
+
+    fn _resize(img: &Mat, size: core::Size, interpolation: i32) -> Result<Mat> {
+        let mut dest = Mat::default();
+        imgproc::resize(img, &mut dest, size, 0.0, 0.0, interpolation)?;
+        Ok(dest)
+    }
+
+    fn _binarize(mask: &Mat) -> Result<Mat> {
+        let mut dest = Mat::default();
+        imgproc::threshold(mask, &mut dest, MASK_THRESHOLD, 255.0, imgproc::THRESH_BINARY)?;
+        Ok(dest)
+    }
+
+    fn _convert(img: &Mat, color_conv: i32) -> Result<Mat> {
+        let mut dest = Mat::default();
+        imgproc::cvt_color(img, &mut dest, color_conv, 0)?;
+        Ok(dest)
+    }
+}
+
+/// A reference to a template: either a file path, loaded through the `Bot` cache, or a preloaded `Template`.
+#[derive(Clone, Copy, Debug)]
+pub enum TemplateRef<'a> {
+    /// Path of an image file.
+    Path(&'a Path),
+    /// Preloaded template.
+    Template(&'a Template),
+}
+
+impl<'a> From<&'a Path> for TemplateRef<'a> {
+    fn from(path: &'a Path) -> Self {
+        TemplateRef::Path(path)
+    }
+}
+
+impl<'a> From<&'a PathBuf> for TemplateRef<'a> {
+    fn from(path: &'a PathBuf) -> Self {
+        TemplateRef::Path(path.as_path())
+    }
+}
+
+impl<'a> From<&'a str> for TemplateRef<'a> {
+    fn from(path: &'a str) -> Self {
+        TemplateRef::Path(Path::new(path))
+    }
+}
+
+impl<'a> From<&'a Template> for TemplateRef<'a> {
+    fn from(template: &'a Template) -> Self {
+        TemplateRef::Template(template)
+    }
+}
+
+/// Cache of the templates loaded from files, keyed by path and modification time.
+#[derive(Debug, Default)]
+pub struct TemplateCache(HashMap<PathBuf, (SystemTime, Template)>);
+
+impl TemplateCache {
+    /// Returns the template of the given file, loading it again only if the file was modified.
+    pub fn get(&mut self, file: &Path) -> Result<Template> {
+        let modified = fs::metadata(file)?.modified()?;
+        if let Some((mtime, template)) = self.0.get(file) {
+            if *mtime == modified {
+                return Ok(template.clone());
+            }
+        }
+        debug!("Loading template {}", file.display());
+        let template = Template::from_file(file)?;
+        self.0.insert(file.to_path_buf(), (modified, template.clone()));
+        Ok(template)
+    }
+
+    /// Resolves a `TemplateRef` into a `Template`.
+    pub fn resolve(&mut self, template: TemplateRef) -> Result<Template> {
+        match template {
+            TemplateRef::Path(file) => self.get(file),
+            TemplateRef::Template(template) => Ok(template.clone()),
+        }
+    }
+
+    /// Empties the cache.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Resized versions of a template, computed once per search.
+#[derive(Debug)]
+pub(crate) struct ScaledTemplates {
+    template: Template,
+    scaled: Vec<(f64, Template)>,
+}
+
+impl ScaledTemplates {
+    /// Creates a new `ScaledTemplates`, resizing the template to the given scales right away.
+    ///
+    /// Scales shrinking the template to nothing are skipped.
+    pub(crate) fn new(template: &Template, scales: &[f64]) -> Result<Self> {
+        let mut scaled = ScaledTemplates { template: template.clone(), scaled: vec![] };
+        for scale in scales {
+            scaled.get(*scale)?;
+        }
+        Ok(scaled)
+    }
+
+    /// Returns the template at its native scale.
+    pub(crate) fn template(&self) -> &Template {
+        &self.template
+    }
+
+    /// Returns the template resized by the given factor, resizing it only on first use,
+    /// or `None` if the factor shrinks the template to nothing.
+    pub(crate) fn get(&mut self, scale: f64) -> Result<Option<Template>> {
+        if let Some((_, template)) = self.scaled.iter().find(|(s, _)| *s == scale) {
+            return Ok(Some(template.clone()));
+        }
+        let (width, height) = self.template.scaled_size(scale);
+        if width == 0 || height == 0 {
+            trace!("Template {} is empty at scale {}", self.template.id(), scale);
+            return Ok(None);
+        }
+        let template = self.template.scaled(scale)?;
+        self.scaled.push((scale, template.clone()));
+        Ok(Some(template))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_channel_becomes_a_mask() {
+        let rounded = Template::from_bytes("rounded", include_bytes!("../tests/fixtures/rounded.png")).unwrap();
+        assert!(rounded.mask().is_some());
+        let opaque_alpha = Template::from_bytes("opaque_alpha", include_bytes!("../tests/fixtures/opaque_alpha.png")).unwrap();
+        assert!(opaque_alpha.mask().is_none());
+        let opaque = Template::from_bytes("button", include_bytes!("../tests/fixtures/button.png")).unwrap();
+        assert!(opaque.mask().is_none());
+    }
+
+    #[test]
+    fn empty_scales_are_skipped() {
+        let button = Template::from_bytes("button", include_bytes!("../tests/fixtures/button.png")).unwrap();
+        assert_eq!(button.scaled_size(0.5), (12, 7));
+        assert_eq!(button.scaled_size(0.03), (1, 0));
+        assert!(button.scaled(0.03).is_err());
+        let mut scaled = ScaledTemplates::new(&button, &[0.03, 0.5]).unwrap();
+        assert!(scaled.get(0.03).unwrap().is_none());
+        assert_eq!(scaled.get(0.5).unwrap().map(|t| t.width()), Some(12));
+    }
+}
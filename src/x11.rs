@@ -0,0 +1,367 @@
+//! X11 backend, based on x11rb and the XTest extension (Linux)
+
+use std::sync::{Arc, OnceLock};
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn};
+
+use enigo::{Key, MouseButton};
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError};
+use x11rb::protocol::xproto::{self, AtomEnum, ConnectionExt as _, ImageFormat, MapState};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+
+use crate::{error, Bounds, Frame, FrameSource, InputDriver, Result, Window, WindowProvider};
+
+const XK_SHIFT_L: u32 = 0xffe1;
+const XK_RETURN: u32 = 0xff0d;
+const XK_TAB: u32 = 0xff09;
+
+/// Lists top-level windows, using `_NET_CLIENT_LIST_STACKING` when a window manager is running.
+///
+/// The listed windows are captured over the connection of `X11Source::shared`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct X11Provider;
+
+impl WindowProvider for X11Provider {
+    fn windows(&self) -> Result<Vec<Window>> {
+        let shared = X11Source::shared()?;
+        let conn = &shared.conn;
+        let root = conn.setup().roots[shared.screen_num].root;
+        let net_client_list = _atom(conn, b"_NET_CLIENT_LIST_STACKING")?;
+        let net_wm_name = _atom(conn, b"_NET_WM_NAME")?;
+        let utf8_string = _atom(conn, b"UTF8_STRING")?;
+
+        let client_list = conn.get_property(false, root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)?.reply()?;
+        let mut ids: Vec<xproto::Window> = client_list.value32().map(|v| v.collect()).unwrap_or_default();
+        if ids.is_empty() {
+            // no window manager (e.g. bare Xvfb): fall back on the children of the root window
+            ids = conn.query_tree(root)?.reply()?.children;
+        }
+        // both lists are in stacking order, bottom to top
+        ids.reverse();
+
+        let source: Arc<dyn FrameSource> = shared.clone();
+        let mut win_list: Vec<Window> = vec![];
+        for id in ids {
+            let (name, owner, bounds, on_screen) = match _window_info(conn, root, id, net_wm_name, utf8_string) {
+                Ok(Some(info)) => info,
+                Ok(None) => continue,
+                // the window may have been destroyed in the meantime
+                Err(ReplyError::X11Error(e)) => {
+                    debug!("Skipping window {}: {:?}", id, e);
+                    continue;
+                },
+                Err(e) => return Err(e.into()),
+            };
+            let mut w = Window::new(id as i64, &name, &owner, Some(bounds));
+            w.set_on_screen(on_screen);
+            w.set_frame_source(source.clone());
+            win_list.push(w);
+        }
+        Ok(win_list)
+    }
+}
+
+// Returns the name, owner, bounds and visibility of a window, `None` if the window has no name
+fn _window_info(conn: &RustConnection, root: xproto::Window, id: xproto::Window, net_wm_name: xproto::Atom, utf8_string: xproto::Atom)
+    -> std::result::Result<Option<(String, String, Bounds, bool)>, ReplyError> {
+    let attributes = conn.get_window_attributes(id)?.reply()?;
+    // unmapped (e.g. minimized) windows are listed as off-screen
+    let on_screen = attributes.map_state == MapState::VIEWABLE;
+    let name = match _string_property(conn, id, net_wm_name, utf8_string)? {
+        Some(name) => name,
+        None => match _string_property(conn, id, AtomEnum::WM_NAME.into(), AtomEnum::ANY.into())? {
+            Some(name) => name,
+            None => return Ok(None),
+        },
+    };
+    // WM_CLASS holds two null-terminated strings: the instance and the class names
+    let owner = _string_property(conn, id, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?
+        .and_then(|class| class.split('\0').nth(1).map(String::from))
+        .unwrap_or_default();
+    let geometry = conn.get_geometry(id)?.reply()?;
+    let origin = conn.translate_coordinates(id, root, 0, 0)?.reply()?;
+    trace!("Window bounds {}, {}, size {} x {}, ", origin.dst_x, origin.dst_y, geometry.height, geometry.width);
+
+    let bounds = Bounds::new(origin.dst_x as f64, origin.dst_y as f64, geometry.width as f64, geometry.height as f64);
+    Ok(Some((name, owner, bounds, on_screen)))
+}
+
+// Connection shared by the provider and the windows without frame source
+static SHARED_SOURCE: OnceLock<Arc<X11Source>> = OnceLock::new();
+
+/// Captures windows with `GetImage`.
+#[derive(Debug)]
+pub struct X11Source {
+    conn: RustConnection,
+    screen_num: usize,
+}
+
+impl X11Source {
+    /// Creates a new `X11Source`, connected to the display of the `DISPLAY` variable.
+    pub fn new() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        Ok(X11Source { conn, screen_num })
+    }
+
+    /// Returns the `X11Source` shared by the whole process, connecting on first use.
+    pub fn shared() -> Result<Arc<X11Source>> {
+        if let Some(source) = SHARED_SOURCE.get() {
+            return Ok(source.clone());
+        }
+        // a thread racing this one may connect too, only one connection is kept
+        let source = Arc::new(X11Source::new()?);
+        Ok(SHARED_SOURCE.get_or_init(|| source).clone())
+    }
+}
+
+impl FrameSource for X11Source {
+    fn capture(&self, window_id: i64) -> Result<Frame> {
+        let window = window_id as xproto::Window;
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        let image = self.conn.get_image(ImageFormat::Z_PIXMAP, window, 0, 0, geometry.width, geometry.height, !0)?.reply()?;
+        let (width, height) = (geometry.width as u32, geometry.height as u32);
+        trace!("img {} x {}, depth {}", width, height, image.depth);
+
+        let mut data = image.data;
+        if data.len() != (width * height * 4) as usize {
+            return Err(error::Error { kind: error::ErrorKind::X11(None), message: format!("Unsupported image of depth {} for window id {}", image.depth, window_id) });
+        }
+        // 24 and 32 bits depth images are stored as BGRX, the padding byte is not always opaque
+        for pixel in data.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+        Frame::new(width, height, data)
+    }
+}
+
+/// Sends mouse and keyboard events with the XTest extension.
+#[derive(Debug)]
+pub struct X11Driver {
+    conn: RustConnection,
+    root: xproto::Window,
+    min_keycode: u8,
+    keysyms_per_keycode: u8,
+    keysyms: Vec<u32>,
+}
+
+impl X11Driver {
+    /// Creates a new `X11Driver`, connected to the display of the `DISPLAY` variable.
+    pub fn new() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        let (min_keycode, max_keycode) = (conn.setup().min_keycode, conn.setup().max_keycode);
+        let mapping = conn.get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?.reply()?;
+        Ok(X11Driver {
+            conn,
+            root,
+            min_keycode,
+            keysyms_per_keycode: mapping.keysyms_per_keycode,
+            keysyms: mapping.keysyms,
+        })
+    }
+
+    fn _fake_input(&self, type_: u8, detail: u8, x: i16, y: i16) -> Result<()> {
+        self.conn.xtest_fake_input(type_, detail, x11rb::CURRENT_TIME, self.root, x, y, 0)?.check()?;
+        Ok(())
+    }
+
+    // Returns the keycode producing the keysym, and whether Shift is needed
+    fn _keycode(&self, keysym: u32) -> Result<(u8, bool)> {
+        let per_keycode = self.keysyms_per_keycode as usize;
+        if per_keycode > 0 {
+            for (index, sym) in self.keysyms.iter().enumerate() {
+                // only the first two levels (plain and shifted) are supported
+                if *sym == keysym && index % per_keycode < 2 {
+                    return Ok((self.min_keycode + (index / per_keycode) as u8, index % per_keycode == 1));
+                }
+            }
+        }
+        Err(error::Error { kind: error::ErrorKind::X11(None), message: format!("No keycode for keysym {:#x}", keysym) })
+    }
+
+    fn _key_keycode(&self, key: Key) -> Result<(u8, bool)> {
+        if let Key::Raw(keycode) = key {
+            return Ok((keycode as u8, false));
+        }
+        match _key_keysym(&key) {
+            Some(keysym) => self._keycode(keysym),
+            None => Err(error::Error { kind: error::ErrorKind::X11(None), message: format!("Unsupported key {:?}", key) }),
+        }
+    }
+
+    fn _press(&mut self, keycode: u8, shift: bool) -> Result<()> {
+        if shift {
+            let (shift_keycode, _) = self._keycode(XK_SHIFT_L)?;
+            self._fake_input(xproto::KEY_PRESS_EVENT, shift_keycode, 0, 0)?;
+        }
+        self._fake_input(xproto::KEY_PRESS_EVENT, keycode, 0, 0)
+    }
+
+    fn _release(&mut self, keycode: u8, shift: bool) -> Result<()> {
+        self._fake_input(xproto::KEY_RELEASE_EVENT, keycode, 0, 0)?;
+        if shift {
+            let (shift_keycode, _) = self._keycode(XK_SHIFT_L)?;
+            self._fake_input(xproto::KEY_RELEASE_EVENT, shift_keycode, 0, 0)?;
+        }
+        Ok(())
+    }
+}
+
+impl InputDriver for X11Driver {
+    fn mouse_move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self._fake_input(xproto::MOTION_NOTIFY_EVENT, 0, x as i16, y as i16)
+    }
+
+    fn mouse_location(&self) -> Result<(i32, i32)> {
+        let pointer = self.conn.query_pointer(self.root)?.reply()?;
+        Ok((pointer.root_x as i32, pointer.root_y as i32))
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+        let button = _button(button)?;
+        self._fake_input(xproto::BUTTON_PRESS_EVENT, button, 0, 0)
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+        let button = _button(button)?;
+        self._fake_input(xproto::BUTTON_RELEASE_EVENT, button, 0, 0)
+    }
+
+    fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        // the wheel is made of buttons 4 to 7, one click per scroll unit
+        let horizontal = if dx < 0 { MouseButton::ScrollLeft } else { MouseButton::ScrollRight };
+        let vertical = if dy < 0 { MouseButton::ScrollUp } else { MouseButton::ScrollDown };
+        for (button, count) in [(horizontal, dx.unsigned_abs()), (vertical, dy.unsigned_abs())] {
+            let button = _button(button)?;
+            for _ in 0..count {
+                self._fake_input(xproto::BUTTON_PRESS_EVENT, button, 0, 0)?;
+                self._fake_input(xproto::BUTTON_RELEASE_EVENT, button, 0, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn key_down(&mut self, key: Key) -> Result<()> {
+        let (keycode, shift) = self._key_keycode(key)?;
+        self._press(keycode, shift)
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<()> {
+        let (keycode, shift) = self._key_keycode(key)?;
+        self._release(keycode, shift)
+    }
+
+    fn key_click(&mut self, key: Key) -> Result<()> {
+        let (keycode, shift) = self._key_keycode(key)?;
+        self._press(keycode, shift)?;
+        self._release(keycode, shift)
+    }
+
+    fn key_sequence(&mut self, text: &str) -> Result<()> {
+        for c in text.chars() {
+            let (keycode, shift) = self._keycode(_char_keysym(c))?;
+            self._press(keycode, shift)?;
+            self._release(keycode, shift)?;
+        }
+        Ok(())
+    }
+}
+
+fn _atom(conn: &RustConnection, name: &[u8]) -> Result<xproto::Atom> {
+    Ok(conn.intern_atom(false, name)?.reply()?.atom)
+}
+
+fn _string_property(conn: &RustConnection, window: xproto::Window, property: xproto::Atom, type_: xproto::Atom)
+    -> std::result::Result<Option<String>, ReplyError> {
+    let reply = conn.get_property(false, window, property, type_, 0, u32::MAX)?.reply()?;
+    if reply.value.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()))
+}
+
+fn _button(button: MouseButton) -> Result<u8> {
+    match button {
+        MouseButton::Left => Ok(1),
+        MouseButton::Middle => Ok(2),
+        MouseButton::Right => Ok(3),
+        MouseButton::ScrollUp => Ok(4),
+        MouseButton::ScrollDown => Ok(5),
+        MouseButton::ScrollLeft => Ok(6),
+        MouseButton::ScrollRight => Ok(7),
+        #[allow(unreachable_patterns)]
+        _ => Err(error::Error { kind: error::ErrorKind::X11(None), message: format!("Unsupported mouse button {:?}", button) }),
+    }
+}
+
+// Latin-1 characters share their keysym value, other Unicode characters are offset by 0x01000000
+fn _char_keysym(c: char) -> u32 {
+    match c {
+        '\n' => XK_RETURN,
+        '\t' => XK_TAB,
+        c if (c as u32) < 0x100 => c as u32,
+        c => 0x0100_0000 | c as u32,
+    }
+}
+
+fn _key_keysym(key: &Key) -> Option<u32> {
+    let keysym = match key {
+        Key::Alt => 0xffe9,
+        Key::Backspace => 0xff08,
+        Key::CapsLock => 0xffe5,
+        Key::Control => 0xffe3,
+        Key::Delete => 0xffff,
+        Key::DownArrow => 0xff54,
+        Key::End => 0xff57,
+        Key::Escape => 0xff1b,
+        Key::F1 => 0xffbe,
+        Key::F2 => 0xffbf,
+        Key::F3 => 0xffc0,
+        Key::F4 => 0xffc1,
+        Key::F5 => 0xffc2,
+        Key::F6 => 0xffc3,
+        Key::F7 => 0xffc4,
+        Key::F8 => 0xffc5,
+        Key::F9 => 0xffc6,
+        Key::F10 => 0xffc7,
+        Key::F11 => 0xffc8,
+        Key::F12 => 0xffc9,
+        Key::Home => 0xff50,
+        Key::LeftArrow => 0xff51,
+        Key::Meta => 0xffeb,
+        Key::PageDown => 0xff56,
+        Key::PageUp => 0xff55,
+        Key::Return => XK_RETURN,
+        Key::RightArrow => 0xff53,
+        Key::Shift => XK_SHIFT_L,
+        Key::Space => 0x0020,
+        Key::Tab => XK_TAB,
+        Key::UpArrow => 0xff52,
+        Key::Layout(c) => _char_keysym(*c),
+        _ => return None,
+    };
+    Some(keysym)
+}
+
+impl From<ConnectError> for error::Error {
+    /// Converts an X11 connection error to this error type.
+    fn from(err: ConnectError) -> error::Error {
+        error::Error { message: err.to_string(), kind: error::ErrorKind::X11(Some(Box::new(err))) }
+    }
+}
+
+impl From<ConnectionError> for error::Error {
+    /// Converts an X11 connection error to this error type.
+    fn from(err: ConnectionError) -> error::Error {
+        error::Error { message: err.to_string(), kind: error::ErrorKind::X11(Some(Box::new(err))) }
+    }
+}
+
+impl From<ReplyError> for error::Error {
+    /// Converts an X11 reply error to this error type.
+    fn from(err: ReplyError) -> error::Error {
+        error::Error { message: err.to_string(), kind: error::ErrorKind::X11(Some(Box::new(err))) }
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::Path;
+use std::time::Duration;
+
+use enigo::MouseButton;
+use macmatic::input::{InputEvent, RecordingDriver};
+use macmatic::{Bot, Bounds, FrameSource, ReplaySource, Window};
+
+fn fixture(path: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(path)
+}
+
+// Bot replaying the dialog fixtures, the button appearing on the 4th frame at (60, 40)
+fn dialog_bot(recorder: &RecordingDriver) -> Bot {
+    let mut bot = Bot::new();
+    bot.set_controller(recorder.clone());
+    bot.set_wait_time(Duration::ZERO);
+    bot.set_high_dpi_ratio(1);
+    let mut window = Window::new(1, "Dialog", "App", Some(Bounds::new(100.0, 50.0, 120.0, 90.0)));
+    window.set_capture_frequency(50.0);
+    bot.window = Some(window);
+    bot.set_frame_source(ReplaySource::from_dir(&fixture("dialog")).unwrap());
+    bot
+}
+
+#[test]
+fn replay_dir_in_numerical_order() {
+    let source = ReplaySource::from_dir(&fixture("replay")).unwrap();
+
+    // the width of each fixture is its expected position
+    let widths: Vec<u32> = (0..4).map(|_| source.capture(0).unwrap().width()).collect();
+    assert_eq!(widths, vec![1, 2, 3, 3]);
+    assert_eq!(source.position(), 3);
+
+    source.rewind();
+    assert_eq!(source.capture(0).unwrap().width(), 1);
+}
+
+#[test]
+fn replay_dir_without_png() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    assert!(ReplaySource::from_dir(&dir).is_err());
+}
+
+#[test]
+fn find_on_replayed_frames() {
+    let recorder = RecordingDriver::new();
+    let mut bot = dialog_bot(&recorder);
+
+    let m = bot.find_match(fixture("button.png").as_path(), Duration::from_secs(2)).unwrap();
+    assert_eq!(m.frame_index, 3);
+    assert_eq!((m.rect.x, m.rect.y, m.rect.width, m.rect.height), (60, 40, 24, 14));
+    assert!(recorder.events().is_empty());
+}
+
+#[test]
+fn click_on_replayed_frames() {
+    let recorder = RecordingDriver::new();
+    let mut bot = dialog_bot(&recorder);
+
+    assert_eq!(bot.click_on_image(fixture("button.png").as_path(), 2000).unwrap(), (72, 47));
+    assert_eq!(recorder.events(), vec![
+        InputEvent::MouseMove { x: 172, y: 97 },
+        InputEvent::MouseDown(MouseButton::Left),
+        InputEvent::MouseUp(MouseButton::Left),
+    ]);
+}